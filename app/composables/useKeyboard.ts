@@ -1,4 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
+import { resolveResource } from "@tauri-apps/api/path";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export interface Keyboard {
   id: {
@@ -6,21 +8,39 @@ export interface Keyboard {
     pid: number;
   };
   path: string;
+  serial_number: string | null;
+  interface_number: number;
   name: string;
   image_path: string;
   keys: Key[];
   key_map_enabled: boolean;
+  /** Null when the model cannot store macros */
+  macro_limits: MacroLimits | null;
   light_enabled: boolean;
   rgb: boolean;
+  /** Whether the firmware can return its configuration (unverified, see `build_read_request`) */
+  read_back: boolean;
   top_left_x: number;
   top_left_y: number;
   bottom_right_x: number;
   bottom_right_y: number;
 }
 
+/**
+ * File path of a keyboard image. Bundled images come as resource paths like
+ * "keyboards/258a/images/52.png", user definitions as absolute paths.
+ */
+export async function resolveKeyboardImage(imagePath: string): Promise<string> {
+  const isAbsolute =
+    imagePath.startsWith("/") || /^[A-Za-z]:[\\/]/.test(imagePath);
+  return isAbsolute ? imagePath : await resolveResource(imagePath);
+}
+
 export interface Key {
   buffer_index: number;
   key_code: number;
+  /** False when the definition uses a key code the app does not know */
+  remappable: boolean;
   top_x: number;
   top_y: number;
   bottom_x: number;
@@ -48,18 +68,224 @@ export interface LightModeConfig {
   custom_colors?: PerKeyColor[];
 }
 
+export type KeyLayer = "base" | "fn";
+
 export interface KeyMapping {
   buffer_index: number;
   key_code: number;
+  /** Defaults to "base" when omitted */
+  layer?: KeyLayer;
+}
+
+export type KeyCategory =
+  | "letters"
+  | "numbers"
+  | "symbols"
+  | "special"
+  | "navigation"
+  | "function"
+  | "modifiers"
+  | "numpad"
+  | "shortcuts"
+  | "media"
+  | "browser";
+
+export interface KeyCodeInfo {
+  name: string;
+  label: string;
+  short_label: string;
+  category: KeyCategory;
+  value: number;
+}
+
+export interface HidUsage {
+  page: number;
+  id: number;
+}
+
+export type KeyNameScheme = "evdev" | "x11" | "kanata";
+
+export interface KeyNames {
+  hid_usage: HidUsage | null;
+  evdev: string | null;
+  x11: string | null;
+  kanata: string | null;
+}
+
+/** Config file format of another remapping tool */
+export type MappingFileFormat = "keyd" | "xmodmap" | "kanata";
+
+export interface MappingExport {
+  text: string;
+  warnings: string[];
+}
+
+export interface MappingImport {
+  mappings: KeyMapping[];
+  warnings: string[];
+}
+
+/** Bit mask of a chord's modifiers: 1 = Ctrl, 2 = Shift, 4 = Alt, 8 = Super */
+export type Modifiers = number;
+
+export type ModifierSide = "left" | "right";
+
+export interface KeyChord {
+  base: number;
+  modifiers: Modifiers;
+  side: ModifierSide;
 }
 
 export interface KeyMappingConfig {
   mappings: KeyMapping[];
 }
 
+export interface MacroLimits {
+  slots: number;
+  max_steps: number;
+}
+
+export type MacroEvent = "key_down" | "key_up";
+
+export interface MacroStep {
+  event: MacroEvent;
+  key_code: number;
+  /** Time to wait after the event */
+  delay_ms: number;
+}
+
+export interface Macro {
+  name: string;
+  steps: MacroStep[];
+}
+
+/** Key code of a key that plays the macro stored in `slot` */
+export function macroKeyCode(slot: number): number {
+  return 0x03000000 | slot;
+}
+
 export interface KeyboardConfig {
   light_mode?: LightModeConfig;
   key_mapping?: KeyMappingConfig;
+  /** Macros indexed by slot */
+  macros?: Macro[];
+}
+
+export interface UnknownCommand {
+  report_index: number;
+  sub_command: number;
+  command: number;
+}
+
+export interface DecodedConfig {
+  config: KeyboardConfig;
+  unknown_commands: UnknownCommand[];
+  errors: string[];
+}
+
+export type TransferKind =
+  | "standard_light"
+  | "custom_light"
+  | "key_map";
+
+/** How out-of-range lighting values are handled before sending */
+export type ValidationPolicy = "reject" | "clamp";
+
+/** Part of a configuration sent by a partial update */
+export type UpdateScope =
+  | "all"
+  | "lighting"
+  | "custom_colors"
+  | "key_map";
+
+export interface DeliveryOptions {
+  report_retries?: number;
+  group_retries?: number;
+  retry_backoff_ms?: number;
+  inter_packet_delay_ms?: number;
+  verify?: boolean;
+  timeout_ms?: number;
+  validation?: ValidationPolicy;
+  scope?: UpdateScope;
+  /** Leave out blocks identical to the last ones applied to the device */
+  skip_unchanged?: boolean;
+}
+
+export interface GroupDelivery {
+  kind: TransferKind;
+  packets: number;
+  attempts: number;
+  verified: boolean | null;
+}
+
+export interface DeliveryReport {
+  groups: GroupDelivery[];
+  superseded: boolean;
+  /** Values adjusted or dropped by validation before sending */
+  warnings: string[];
+  /** Blocks left out because the device already has them */
+  skipped: TransferKind[];
+}
+
+/** Delivery failure, shaped like `AppError` with the failing group in `details` */
+export interface DeliveryError {
+  code: string;
+  message: string;
+  details: {
+    group_index: number;
+    kind: TransferKind | null;
+    report_index: number | null;
+    attempts: number;
+    delivered: GroupDelivery[];
+    [key: string]: unknown;
+  };
+}
+
+export interface DeviceSendResult {
+  path: string;
+  serial_number: string | null;
+  error: DeliveryError | null;
+}
+
+/** Error returned by the backend */
+export interface AppError {
+  code: string;
+  message: string;
+  details: Record<string, unknown> | null;
+}
+
+/** Keyboard model the app has a definition for */
+export interface SupportedKeyboard {
+  id: {
+    vid: number;
+    pid: number;
+  };
+  name: string;
+  image_path: string;
+  key_map_enabled: boolean;
+  light_enabled: boolean;
+  rgb: boolean;
+  definition_path: string;
+  /** False when the definition comes from a user definitions directory */
+  bundled: boolean;
+}
+
+export interface SupportedKeyboards {
+  keyboards: SupportedKeyboard[];
+  /** Definitions that could not be loaded, with the file in `details.path` */
+  failures: AppError[];
+}
+
+/** New state of a definition whose files changed on disk */
+export interface DefinitionChange {
+  id: {
+    vid: number;
+    pid: number;
+  };
+  /** Layout now used for the model (with an empty `path`), null when unsupported */
+  keyboard: Keyboard | null;
+  /** Why the definition could not be loaded, if it is broken */
+  error: AppError | null;
 }
 
 export interface LightingMode {
@@ -72,25 +298,147 @@ export const useKeyboard = () => {
     return await invoke<Keyboard[]>("scan_keyboards");
   };
 
+  const listSupportedKeyboards = async (): Promise<SupportedKeyboards> => {
+    return await invoke<SupportedKeyboards>("list_supported_keyboards");
+  };
+
   const sendKeyboardConfig = async (
     keyboardPath: string,
     config: KeyboardConfig,
-    keyboard: Keyboard
-  ): Promise<void> => {
-    return await invoke("send_keyboard_config", {
+    keyboard: Keyboard,
+    options?: DeliveryOptions
+  ): Promise<DeliveryReport> => {
+    return await invoke<DeliveryReport>("send_keyboard_config", {
       keyboardPath,
       config,
       keyboard,
+      options,
+    });
+  };
+
+  const sendKeyboardConfigToAll = async (
+    config: KeyboardConfig,
+    keyboard: Keyboard,
+    options?: DeliveryOptions
+  ): Promise<DeviceSendResult[]> => {
+    return await invoke<DeviceSendResult[]>("send_keyboard_config_to_all", {
+      config,
+      keyboard,
+      options,
     });
   };
 
+  const cancelDeviceOperations = async (keyboardPath: string): Promise<void> => {
+    await invoke("cancel_device_operations", { keyboardPath });
+  };
+
   const getLightingModes = async (isRgb: boolean): Promise<LightingMode[]> => {
     return await invoke<LightingMode[]>("get_lighting_modes", { isRgb });
   };
 
+  const listKeyCodes = async (): Promise<KeyCodeInfo[]> => {
+    return await invoke<KeyCodeInfo[]>("list_key_codes");
+  };
+
+  const composeKeyCode = async (
+    base: number,
+    modifiers: Modifiers,
+    side: ModifierSide
+  ): Promise<number> => {
+    return await invoke<number>("compose_key_code", { base, modifiers, side });
+  };
+
+  const decomposeKeyCode = async (code: number): Promise<KeyChord | null> => {
+    return await invoke<KeyChord | null>("decompose_key_code", { code });
+  };
+
+  const keyCodeNames = async (code: number): Promise<KeyNames> => {
+    return await invoke<KeyNames>("key_code_names", { code });
+  };
+
+  const keyCodeFromName = async (
+    scheme: KeyNameScheme,
+    name: string
+  ): Promise<number | null> => {
+    return await invoke<number | null>("key_code_from_name", { scheme, name });
+  };
+
+  const exportKeyMappings = async (
+    keyboard: Keyboard,
+    keyMapping: KeyMappingConfig,
+    format: MappingFileFormat
+  ): Promise<MappingExport> => {
+    return await invoke<MappingExport>("export_key_mappings", {
+      keyboard,
+      keyMapping,
+      format,
+    });
+  };
+
+  const importKeyMappings = async (
+    keyboard: Keyboard,
+    format: MappingFileFormat,
+    text: string
+  ): Promise<MappingImport> => {
+    return await invoke<MappingImport>("import_key_mappings", {
+      keyboard,
+      format,
+      text,
+    });
+  };
+
+  const decodeFeatureReports = async (
+    keyboard: Keyboard,
+    dump: string
+  ): Promise<DecodedConfig> => {
+    return await invoke<DecodedConfig>("decode_feature_reports", {
+      keyboard,
+      dump,
+    });
+  };
+
+  const onKeyboardConnected = async (
+    handler: (keyboard: Keyboard) => void
+  ): Promise<UnlistenFn> => {
+    return await listen<Keyboard>("keyboard-connected", (event) =>
+      handler(event.payload)
+    );
+  };
+
+  const onKeyboardDisconnected = async (
+    handler: (keyboard: Keyboard) => void
+  ): Promise<UnlistenFn> => {
+    return await listen<Keyboard>("keyboard-disconnected", (event) =>
+      handler(event.payload)
+    );
+  };
+
+  const onKeyboardDefinitionChanged = async (
+    handler: (change: DefinitionChange) => void
+  ): Promise<UnlistenFn> => {
+    return await listen<DefinitionChange>(
+      "keyboard-definition-changed",
+      (event) => handler(event.payload)
+    );
+  };
+
   return {
     scanKeyboards,
+    listSupportedKeyboards,
     sendKeyboardConfig,
+    sendKeyboardConfigToAll,
+    cancelDeviceOperations,
     getLightingModes,
+    listKeyCodes,
+    composeKeyCode,
+    decomposeKeyCode,
+    keyCodeNames,
+    keyCodeFromName,
+    exportKeyMappings,
+    importKeyMappings,
+    decodeFeatureReports,
+    onKeyboardConnected,
+    onKeyboardDisconnected,
+    onKeyboardDefinitionChanged,
   };
 };
@@ -0,0 +1,50 @@
+//! Check keyboard definition files for mistakes the app cannot recover from.
+//!
+//! Usage: `rk-defs-lint [KEYBOARDS_DIR]`. The directory defaults to the one the app
+//! loads definitions from. Exits with status 1 when any file has an error.
+
+use rk_configurator_lib::keyboard::get_resource_dir;
+use rk_configurator_lib::lint::{lint_definitions, Severity};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let root = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(get_resource_dir);
+
+    let reports = match lint_definitions(&root) {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for report in &reports {
+        for issue in &report.issues {
+            match issue.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+            println!("{}: {}", report.path.display(), issue);
+        }
+    }
+
+    println!(
+        "Checked {} definition files: {} errors, {} warnings",
+        reports.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
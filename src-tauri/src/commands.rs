@@ -1,51 +1,98 @@
-use crate::hid::HidManager;
-use crate::models::{Keyboard, KeyboardConfig, Mode};
+use crate::decoder::{decode_buffers, parse_hex_dump, DecodedConfig};
+use crate::delivery::{DeliveryError, DeliveryOptions, DeliveryReport};
+use crate::error::AppError;
+use crate::hid::{DeviceSendResult, HidManager};
+use crate::interop::{
+    export_mappings, import_mappings, KeyNameScheme, KeyNames, MappingExport, MappingFileFormat,
+    MappingImport,
+};
+use crate::models::{
+    self, KeyChord, KeyCode, KeyCodeInfo, KeyMappingConfig, Keyboard, KeyboardConfig, Mode,
+    ModifierSide, Modifiers,
+};
 use crate::modes::{get_rgb_modes, get_single_color_modes};
-use crate::protocol::build_buffers;
-use std::sync::{Arc, Mutex};
-use tauri::{AppHandle, State};
+use crate::registry::SupportedKeyboards;
+use std::sync::Arc;
+use tauri::State;
 
-/// Global HID manager state
-pub type HidManagerState = Arc<Mutex<HidManager>>;
+/// Global HID manager state (synchronized internally, so no outer lock is needed)
+pub type HidManagerState = Arc<HidManager>;
+
+/// Run blocking HID work on the blocking thread pool so the main thread stays responsive.
+///
+/// Device requests time out inside the per-device workers; a panic in `operation`
+/// is reported as an error instead of taking the command down.
+async fn run_blocking<T: Send + 'static>(
+    hid_manager: &HidManagerState,
+    operation: impl FnOnce(&HidManager) -> T + Send + 'static,
+) -> Result<T, AppError> {
+    let manager = hid_manager.clone();
+    tauri::async_runtime::spawn_blocking(move || operation(&manager))
+        .await
+        .map_err(|e| AppError::Internal(format!("HID task failed: {}", e)))
+}
 
 /// Scan for connected keyboards
 #[tauri::command]
-pub fn scan_keyboards(
-    _app: AppHandle,
+pub async fn scan_keyboards(
     hid_manager: State<'_, HidManagerState>,
-) -> Result<Vec<Keyboard>, String> {
-    let manager = hid_manager.lock().unwrap();
-    manager.scan_keyboards()
+) -> Result<Vec<Keyboard>, AppError> {
+    run_blocking(&hid_manager, |manager| manager.scan_keyboards()).await?
+}
+
+/// List every keyboard model with a definition, and the definitions that failed to load
+#[tauri::command]
+pub fn list_supported_keyboards(hid_manager: State<'_, HidManagerState>) -> SupportedKeyboards {
+    hid_manager.supported_keyboards()
 }
 
 /// Send keyboard configuration to device
 #[tauri::command]
-pub fn send_keyboard_config(
+pub async fn send_keyboard_config(
     keyboard_path: String,
     config: KeyboardConfig,
     keyboard: Keyboard,
+    options: Option<DeliveryOptions>,
     hid_manager: State<'_, HidManagerState>,
-) -> Result<(), String> {
-    let manager = hid_manager.lock().unwrap();
-
-    // Build protocol buffers
-    let buffers = build_buffers(&keyboard, &config)
-        .map_err(|e| format!("Failed to build protocol buffers: {}", e))?;
+) -> Result<DeliveryReport, DeliveryError> {
+    run_blocking(&hid_manager, move |manager| {
+        manager.send_config(
+            &keyboard_path,
+            &keyboard,
+            &config,
+            &options.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(DeliveryError::before_send)?
+}
 
-    // Send buffers to device
-    manager
-        .send_buffers(&keyboard_path, &buffers)
-        .map_err(|e| format!("Failed to send configuration: {}", e))?;
+/// Send keyboard configuration to every connected unit of the same model
+#[tauri::command]
+pub async fn send_keyboard_config_to_all(
+    config: KeyboardConfig,
+    keyboard: Keyboard,
+    options: Option<DeliveryOptions>,
+    hid_manager: State<'_, HidManagerState>,
+) -> Result<Vec<DeviceSendResult>, AppError> {
+    run_blocking(&hid_manager, move |manager| {
+        manager.send_config_to_all(&keyboard, &config, &options.unwrap_or_default())
+    })
+    .await?
+}
 
-    Ok(())
+/// Drop the requests queued for a device that have not been sent yet
+#[tauri::command]
+pub fn cancel_device_operations(keyboard_path: String, hid_manager: State<'_, HidManagerState>) {
+    hid_manager.cancel_pending(&keyboard_path);
 }
 
 // Database operations are handled in the frontend using tauri-plugin-sql JavaScript API
 
 /// Initialize HID manager (called on app startup)
-pub fn init_hid_manager() -> Result<HidManagerState, String> {
+pub fn init_hid_manager() -> Result<HidManagerState, AppError> {
     let manager = HidManager::new()?;
-    Ok(Arc::new(Mutex::new(manager)))
+    Ok(Arc::new(manager))
 }
 
 /// Get available lighting modes for a keyboard
@@ -57,3 +104,66 @@ pub fn get_lighting_modes(is_rgb: bool) -> Vec<Mode> {
         get_single_color_modes()
     }
 }
+
+/// List every named key code with its labels and category, for the mapping editor
+#[tauri::command]
+pub fn list_key_codes() -> Vec<KeyCodeInfo> {
+    models::list_key_codes()
+}
+
+/// Build the key code of a chord such as Ctrl+Shift+T
+#[tauri::command]
+pub fn compose_key_code(
+    base: KeyCode,
+    modifiers: Modifiers,
+    side: ModifierSide,
+) -> Result<KeyCode, AppError> {
+    KeyCode::with_modifiers(base, modifiers, side).ok_or_else(|| {
+        AppError::InvalidConfig(format!("{:?} cannot be combined with modifiers", base))
+    })
+}
+
+/// Split a key code into its key and modifiers, if it is a chord
+#[tauri::command]
+pub fn decompose_key_code(code: KeyCode) -> Option<KeyChord> {
+    code.chord()
+}
+
+/// HID usage, evdev and X11 names of a key code, for exporting mappings to other tools
+#[tauri::command]
+pub fn key_code_names(code: KeyCode) -> KeyNames {
+    code.names()
+}
+
+/// Key code for a key name of another tool, for importing mappings
+#[tauri::command]
+pub fn key_code_from_name(scheme: KeyNameScheme, name: String) -> Option<KeyCode> {
+    KeyCode::from_name(scheme, &name)
+}
+
+/// Write the base layer remaps of a key mapping as a keyd, xmodmap or kanata config
+#[tauri::command]
+pub fn export_key_mappings(
+    keyboard: Keyboard,
+    key_mapping: KeyMappingConfig,
+    format: MappingFileFormat,
+) -> MappingExport {
+    export_mappings(&keyboard, &key_mapping, format)
+}
+
+/// Read the remaps of a keyd, xmodmap or kanata config as base layer mappings
+#[tauri::command]
+pub fn import_key_mappings(
+    keyboard: Keyboard,
+    format: MappingFileFormat,
+    text: String,
+) -> Result<MappingImport, AppError> {
+    import_mappings(&keyboard, format, &text).map_err(AppError::InvalidConfig)
+}
+
+/// Decode a hex dump of captured feature reports (one report per line) into a configuration
+#[tauri::command]
+pub fn decode_feature_reports(keyboard: Keyboard, dump: String) -> Result<DecodedConfig, AppError> {
+    let reports = parse_hex_dump(&dump).map_err(AppError::Protocol)?;
+    Ok(decode_buffers(&keyboard, &reports))
+}
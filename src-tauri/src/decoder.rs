@@ -0,0 +1,371 @@
+use crate::emulator::{EmulatedKeyboard, ProtocolError};
+use crate::models::{
+    KeyCode, KeyLayer, KeyMapping, KeyMappingConfig, Keyboard, KeyboardConfig, PerKeyColor,
+};
+use crate::modes::is_custom_mode;
+use crate::protocol::BUFFER_SIZE;
+use serde::Serialize;
+
+/// First packet of a transfer whose command bytes are not understood
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnknownCommand {
+    /// Position of the report in the decoded sequence
+    pub report_index: usize,
+    pub sub_command: u8,
+    pub command: u8,
+}
+
+/// Result of decoding a sequence of feature reports
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedConfig {
+    pub config: KeyboardConfig,
+    pub unknown_commands: Vec<UnknownCommand>,
+    /// Other protocol violations (truncated or out-of-order transfers, malformed reports)
+    pub errors: Vec<String>,
+}
+
+/// Reconstruct the `KeyboardConfig` carried by a sequence of 65-byte feature reports
+pub fn decode_buffers(keyboard: &Keyboard, reports: &[Vec<u8>]) -> DecodedConfig {
+    let emulator = EmulatedKeyboard::new();
+    let mut unknown_commands = Vec::new();
+
+    for (report_index, report) in reports.iter().enumerate() {
+        if let Err(ProtocolError::UnknownCommand {
+            sub_command,
+            command,
+        }) = emulator.handle_report(report)
+        {
+            unknown_commands.push(UnknownCommand {
+                report_index,
+                sub_command,
+                command,
+            });
+        }
+    }
+
+    let mut errors: Vec<String> = emulator
+        .errors()
+        .iter()
+        .filter(|e| !matches!(e, ProtocolError::UnknownCommand { .. }))
+        .map(|e| e.to_string())
+        .collect();
+
+    if emulator.has_pending_transfer() {
+        errors.push("Last transfer is incomplete".to_string());
+    }
+
+    let mut light_mode = emulator.light_mode();
+
+    if let Some(colors) = emulator.custom_colors() {
+        match light_mode.as_mut() {
+            Some(light_config) => {
+                if !is_custom_mode(light_config.mode_bit, keyboard.rgb) {
+                    errors.push(format!(
+                        "Custom colors sent with non-custom mode {}",
+                        light_config.mode_bit
+                    ));
+                }
+
+                light_config.custom_colors = Some(
+                    keyboard
+                        .keys
+                        .iter()
+                        .filter_map(|key| {
+                            colors
+                                .get(key.buffer_index as usize)
+                                .map(|color| PerKeyColor {
+                                    buffer_index: key.buffer_index,
+                                    color: *color,
+                                })
+                        })
+                        .collect(),
+                );
+            }
+            None => errors.push("Custom colors sent without a light mode packet".to_string()),
+        }
+    }
+
+    // Only keys that differ from the keyboard defaults are reported as mappings
+    let key_mapping = emulator.key_map().map(|codes| KeyMappingConfig {
+        mappings: keyboard
+            .keys
+            .iter()
+            .filter_map(|key| {
+                let code = *codes.get(key.buffer_index as usize)?;
+                (code != key.key_code.to_u32()).then(|| KeyMapping {
+                    buffer_index: key.buffer_index,
+                    key_code: KeyCode::from_u32(code),
+                    layer: KeyLayer::Base,
+                })
+            })
+            .collect(),
+    });
+
+    DecodedConfig {
+        config: KeyboardConfig {
+            light_mode,
+            key_mapping,
+            macros: None,
+        },
+        unknown_commands,
+        errors,
+    }
+}
+
+/// Parse a hex dump with one feature report per line.
+///
+/// Bytes may be separated by whitespace, `:` or `,` and optionally prefixed with `0x`,
+/// so dumps copied from Wireshark or usbmon can be pasted as is.
+pub fn parse_hex_dump(dump: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut reports = Vec::new();
+
+    for (line_number, line) in dump.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let report =
+            parse_hex_report(line).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Parse a single 65-byte feature report from hex
+pub fn parse_hex_report(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: String = hex
+        .split(|c: char| c.is_whitespace() || c == ':' || c == ',')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex character: {}", c));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err("Odd number of hex digits".to_string());
+    }
+
+    let report = (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex byte: {}", &digits[i..i + 2]))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+
+    if report.len() != BUFFER_SIZE {
+        return Err(format!(
+            "Report has incorrect size: expected {}, got {}",
+            BUFFER_SIZE,
+            report.len()
+        ));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Key, LightModeConfig, RgbColor};
+    use crate::protocol::build_transfer_groups;
+    use crate::test_support::{key, keyboard};
+
+    fn keys() -> Vec<Key> {
+        vec![
+            key(0, KeyCode::KeyEscape),
+            key(6, KeyCode::KeyA),
+            key(12, KeyCode::KeyF1),
+        ]
+    }
+
+    fn light_mode(mode_bit: u8, custom_colors: Option<Vec<PerKeyColor>>) -> LightModeConfig {
+        LightModeConfig {
+            mode_bit,
+            animation: 2,
+            brightness: 5,
+            color: Some(RgbColor {
+                r: 0x10,
+                g: 0x20,
+                b: 0x30,
+            }),
+            random_colors: true,
+            sleep: 4,
+            custom_colors,
+        }
+    }
+
+    fn encode(keyboard: &Keyboard, config: &KeyboardConfig) -> Vec<Vec<u8>> {
+        build_transfer_groups(keyboard, config)
+            .unwrap()
+            .into_iter()
+            .flat_map(|group| group.reports)
+            .collect()
+    }
+
+    #[test]
+    fn lighting_round_trips() {
+        let keyboard = keyboard(keys());
+        let config = KeyboardConfig {
+            light_mode: Some(light_mode(16, None)),
+            key_mapping: None,
+            macros: None,
+        };
+
+        let decoded = decode_buffers(&keyboard, &encode(&keyboard, &config));
+
+        assert!(decoded.errors.is_empty());
+        assert!(decoded.unknown_commands.is_empty());
+        let light = decoded.config.light_mode.unwrap();
+        assert_eq!(
+            (
+                light.mode_bit,
+                light.animation,
+                light.brightness,
+                light.random_colors,
+                light.sleep
+            ),
+            (16, 2, 5, true, 4)
+        );
+        let color = light.color.unwrap();
+        assert_eq!((color.r, color.g, color.b), (0x10, 0x20, 0x30));
+        assert!(light.custom_colors.is_none());
+        assert!(decoded.config.key_mapping.is_none());
+    }
+
+    #[test]
+    fn custom_colors_round_trip() {
+        let keyboard = keyboard(keys());
+        let colors = vec![
+            PerKeyColor {
+                buffer_index: 6,
+                color: RgbColor {
+                    r: 0xff,
+                    g: 0,
+                    b: 0x7f,
+                },
+            },
+            PerKeyColor {
+                buffer_index: 12,
+                color: RgbColor { r: 1, g: 2, b: 3 },
+            },
+        ];
+        let config = KeyboardConfig {
+            light_mode: Some(light_mode(0, Some(colors))),
+            key_mapping: None,
+            macros: None,
+        };
+
+        let decoded = decode_buffers(&keyboard, &encode(&keyboard, &config));
+
+        assert!(decoded.errors.is_empty());
+        // Every key of the layout is reported, unset ones as black
+        let colors: Vec<(u8, u8, u8, u8)> = decoded
+            .config
+            .light_mode
+            .unwrap()
+            .custom_colors
+            .unwrap()
+            .iter()
+            .map(|c| (c.buffer_index, c.color.r, c.color.g, c.color.b))
+            .collect();
+        assert_eq!(colors, [(0, 0, 0, 0), (6, 0xff, 0, 0x7f), (12, 1, 2, 3)]);
+    }
+
+    #[test]
+    fn key_map_round_trips_changed_keys_only() {
+        let keyboard = keyboard(keys());
+        let config = KeyboardConfig {
+            light_mode: None,
+            key_mapping: Some(KeyMappingConfig {
+                mappings: vec![
+                    KeyMapping {
+                        buffer_index: 0,
+                        key_code: KeyCode::KeyCapsLock,
+                        layer: KeyLayer::Base,
+                    },
+                    // Mapped to its own default, so not a change
+                    KeyMapping {
+                        buffer_index: 6,
+                        key_code: KeyCode::KeyA,
+                        layer: KeyLayer::Base,
+                    },
+                    KeyMapping {
+                        buffer_index: 12,
+                        key_code: KeyCode::from_u32(0x0012_3400),
+                        layer: KeyLayer::Base,
+                    },
+                ],
+            }),
+            macros: None,
+        };
+
+        let decoded = decode_buffers(&keyboard, &encode(&keyboard, &config));
+
+        assert!(decoded.errors.is_empty());
+        let mappings: Vec<(u8, KeyCode, KeyLayer)> = decoded
+            .config
+            .key_mapping
+            .unwrap()
+            .mappings
+            .iter()
+            .map(|m| (m.buffer_index, m.key_code, m.layer))
+            .collect();
+        assert_eq!(
+            mappings,
+            [
+                (0, KeyCode::KeyCapsLock, KeyLayer::Base),
+                (12, KeyCode::from_u32(0x0012_3400), KeyLayer::Base)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_commands_and_incomplete_transfers_are_reported() {
+        let keyboard = keyboard(keys());
+        let config = KeyboardConfig {
+            light_mode: Some(light_mode(16, None)),
+            key_mapping: Some(KeyMappingConfig {
+                mappings: Vec::new(),
+            }),
+            macros: None,
+        };
+        let mut reports = encode(&keyboard, &config);
+        reports[0][4] = 0x42;
+        reports.pop();
+
+        let decoded = decode_buffers(&keyboard, &reports);
+
+        assert_eq!(
+            decoded.unknown_commands,
+            [UnknownCommand {
+                report_index: 0,
+                sub_command: 0x02,
+                command: 0x42
+            }]
+        );
+        assert_eq!(decoded.errors, ["Last transfer is incomplete"]);
+        assert!(decoded.config.light_mode.is_none());
+        assert!(decoded.config.key_mapping.is_none());
+    }
+
+    #[test]
+    fn hex_dump_accepts_common_separators() {
+        let report: Vec<String> = (0..BUFFER_SIZE).map(|i| format!("{:02x}", i)).collect();
+        let dump = format!(
+            "# captured\n{}\n\n0x{}\n",
+            report.join(":"),
+            report.join(", 0x")
+        );
+
+        let reports = parse_hex_dump(&dump).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0], (0..BUFFER_SIZE as u8).collect::<Vec<u8>>());
+        assert_eq!(reports[0], reports[1]);
+        assert!(parse_hex_report("0a 01").is_err());
+        assert!(parse_hex_dump("0a zz").unwrap_err().starts_with("Line 1:"));
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Deserialize;
+
+/// Point of the keyboard image, as `[x, y]` in image pixels
+pub type Point = [i32; 2];
+
+/// Keyboard definition file (`keyboards/<vid>/configs/<pid>.json`).
+///
+/// The format is described by `keyboards/definition.schema.json`; keep both in sync.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyboardDefinition {
+    /// Schema reference for editors, ignored by the app
+    #[serde(rename = "$schema", default)]
+    pub schema: Option<String>,
+    /// Product ID in lowercase hex, matching the file name
+    pub pid: String,
+    pub name: String,
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub key_map_enabled: bool,
+    #[serde(default)]
+    pub light_enabled: bool,
+    #[serde(default)]
+    pub rgb: bool,
+    /// Top-left corner of the key area
+    pub top: Point,
+    /// Bottom-right corner of the key area
+    pub bottom: Point,
+    /// Keys of the layout; may only be omitted when the key map is disabled
+    #[serde(default)]
+    pub keys: Vec<KeyDefinition>,
+    /// Macro storage limits of the model (no macro support when omitted), used to
+    /// validate macros
+    #[serde(default)]
+    pub macros: Option<MacroDefinition>,
+    /// Firmware answers block read requests. The read request format is not taken from
+    /// a capture, so it is only sent to models confirmed to answer it
+    #[serde(default)]
+    pub read_back: bool,
+}
+
+/// Key of a definition file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyDefinition {
+    /// Position of the key in the key map and per-key color blocks
+    #[serde(rename = "bIndex")]
+    pub b_index: u8,
+    /// Name of the key code, e.g. `Key_Escape`
+    pub key_code: String,
+    pub top: Point,
+    pub bottom: Point,
+}
+
+/// Macro storage limits of a definition file
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MacroDefinition {
+    pub slots: u8,
+    pub max_steps: u8,
+}
+
+/// Parse a definition file.
+///
+/// Type errors carry the line and column of the offending value. Fields the format
+/// does not define are returned as paths for the caller to reject or report.
+pub fn parse_definition_json(content: &str) -> Result<(KeyboardDefinition, Vec<String>), String> {
+    let mut unknown_fields = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(content);
+
+    let definition = serde_ignored::deserialize(&mut deserializer, |path| {
+        unknown_fields.push(path.to_string());
+    })
+    .and_then(|definition| deserializer.end().map(|()| definition))
+    .map_err(|e| format!("Failed to parse config JSON: {}", e))?;
+
+    Ok((definition, unknown_fields))
+}
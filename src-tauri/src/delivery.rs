@@ -0,0 +1,528 @@
+use crate::error::{AppError, ErrorCode};
+use crate::models::KeyboardConfig;
+use crate::protocol::{TransferGroup, TransferKind, BUFFER_SIZE};
+use crate::transport::HidConnection;
+use crate::validation::ValidationPolicy;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// Retry and pacing settings used when sending transfers to a keyboard
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryOptions {
+    /// Extra attempts for a single report before its group is considered failed
+    pub report_retries: u32,
+    /// Times a failed group is re-sent from its first packet
+    pub group_retries: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub retry_backoff_ms: u64,
+    /// Pause between two consecutive reports, for slow firmware
+    pub inter_packet_delay_ms: u64,
+    /// Read every group back after sending it and re-send it on mismatch
+    pub verify: bool,
+    /// Give up waiting for the device after this long (the queued write is dropped if not started)
+    pub timeout_ms: u64,
+    /// How out-of-range lighting values are handled before sending
+    pub validation: ValidationPolicy,
+    /// Part of the configuration to send
+    pub scope: UpdateScope,
+    /// Leave out blocks identical to the last ones applied to the device
+    pub skip_unchanged: bool,
+}
+
+impl Default for DeliveryOptions {
+    fn default() -> Self {
+        Self {
+            report_retries: 2,
+            group_retries: 1,
+            retry_backoff_ms: 20,
+            inter_packet_delay_ms: 0,
+            verify: false,
+            timeout_ms: 10_000,
+            validation: ValidationPolicy::Reject,
+            scope: UpdateScope::All,
+            skip_unchanged: false,
+        }
+    }
+}
+
+impl DeliveryOptions {
+    /// Delay to wait before the given retry (0-based)
+    fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.retry_backoff_ms.saturating_mul(1u64 << retry.min(16)))
+    }
+
+    /// Time to wait for the whole delivery
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// Part of a configuration sent by a partial update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateScope {
+    #[default]
+    All,
+    /// Standard light packet only (mode, brightness, speed, color)
+    Lighting,
+    /// Per-key color block only
+    CustomColors,
+    /// Key map block
+    KeyMap,
+}
+
+impl UpdateScope {
+    /// Whether transfers of `kind` belong to this part
+    pub fn includes(self, kind: TransferKind) -> bool {
+        match self {
+            UpdateScope::All => true,
+            UpdateScope::Lighting => kind == TransferKind::StandardLight,
+            UpdateScope::CustomColors => kind == TransferKind::CustomLight,
+            UpdateScope::KeyMap => kind == TransferKind::KeyMap,
+        }
+    }
+
+    /// Parts of `config` this scope sends, so the others are neither built nor validated
+    pub fn select(self, config: &KeyboardConfig) -> KeyboardConfig {
+        let mut light_mode = config.light_mode.clone().filter(|_| {
+            self.includes(TransferKind::StandardLight) || self.includes(TransferKind::CustomLight)
+        });
+        if !self.includes(TransferKind::CustomLight) {
+            if let Some(light_mode) = light_mode.as_mut() {
+                light_mode.custom_colors = None;
+            }
+        }
+
+        KeyboardConfig {
+            light_mode,
+            key_mapping: config
+                .key_mapping
+                .clone()
+                .filter(|_| self.includes(TransferKind::KeyMap)),
+            macros: config.macros.clone().filter(|_| self == UpdateScope::All),
+        }
+    }
+}
+
+/// Outcome of one delivered transfer group
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupDelivery {
+    pub kind: TransferKind,
+    pub packets: usize,
+    /// Number of times the whole group was sent
+    pub attempts: u32,
+    /// Read-back result; `None` when not requested or unsupported by the firmware
+    pub verified: Option<bool>,
+}
+
+/// Outcome of a successful delivery
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeliveryReport {
+    pub groups: Vec<GroupDelivery>,
+    /// Nothing was sent because a newer lighting update was queued for the same device
+    pub superseded: bool,
+    /// Values adjusted or dropped by validation before sending
+    pub warnings: Vec<String>,
+    /// Blocks left out because the device already has them (`skip_unchanged`)
+    pub skipped: Vec<TransferKind>,
+}
+
+/// Delivery failure, telling which group could not be written.
+///
+/// Serialized like `AppError` as `{ code, message, details }`, with the delivery fields
+/// (and those of the underlying error) in `details`.
+#[derive(Debug, Clone)]
+pub struct DeliveryError {
+    /// Index of the failed group in the transfer sequence
+    pub group_index: usize,
+    /// Kind of the failed group (`None` when the device could not be opened)
+    pub kind: Option<TransferKind>,
+    /// Report of the group that failed last (`None` for verification failures)
+    pub report_index: Option<usize>,
+    pub attempts: u32,
+    /// Kind of the underlying failure
+    pub code: ErrorCode,
+    pub message: String,
+    /// Structured fields of the underlying error (`AppError::details`)
+    pub cause: Option<Value>,
+    /// Groups that were fully delivered before the failure
+    pub delivered: Vec<GroupDelivery>,
+}
+
+impl DeliveryError {
+    /// Failure that happened before any group was sent
+    pub fn before_send(error: AppError) -> Self {
+        Self {
+            group_index: 0,
+            kind: None,
+            report_index: None,
+            attempts: 0,
+            code: error.code(),
+            message: error.to_string(),
+            cause: error.details(),
+            delivered: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind, self.report_index) {
+            (Some(kind), Some(report_index)) => write!(
+                f,
+                "Failed to send {:?} report {} after {} attempts: {}",
+                kind, report_index, self.attempts, self.message
+            ),
+            (Some(kind), None) => write!(
+                f,
+                "Failed to deliver {:?} transfer after {} attempts: {}",
+                kind, self.attempts, self.message
+            ),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Serialize for DeliveryError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut details = json!({
+            "group_index": self.group_index,
+            "kind": self.kind,
+            "report_index": self.report_index,
+            "attempts": self.attempts,
+            "delivered": self.delivered,
+        });
+        if let (Some(Value::Object(cause)), Value::Object(details)) = (&self.cause, &mut details) {
+            for (key, value) in cause {
+                details.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        let mut state = serializer.serialize_struct("DeliveryError", 3)?;
+        state.serialize_field("code", &self.code)?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &details)?;
+        state.end()
+    }
+}
+
+/// Send transfer groups with all-or-nothing semantics per group.
+///
+/// Each report is retried with exponential backoff; when it still fails, the whole
+/// group is re-sent from its first packet so the firmware never keeps a half-written
+/// transfer. `read_back` is used for verification and returns `None` when the
+/// firmware cannot answer.
+pub fn deliver(
+    device: &dyn HidConnection,
+    groups: &[TransferGroup],
+    options: &DeliveryOptions,
+    read_back: impl Fn(&dyn HidConnection, TransferKind) -> Option<Vec<Vec<u8>>>,
+) -> Result<DeliveryReport, DeliveryError> {
+    let mut report = DeliveryReport::default();
+
+    for (group_index, group) in groups.iter().enumerate() {
+        let fail = |report_index: Option<usize>,
+                    attempts: u32,
+                    error: AppError,
+                    delivered: Vec<GroupDelivery>| {
+            DeliveryError {
+                group_index,
+                kind: Some(group.kind),
+                report_index,
+                attempts,
+                code: error.code(),
+                message: error.to_string(),
+                cause: error.details(),
+                delivered,
+            }
+        };
+
+        if let Some((i, buffer)) = group
+            .reports
+            .iter()
+            .enumerate()
+            .find(|(_, buffer)| buffer.len() != BUFFER_SIZE)
+        {
+            return Err(fail(
+                Some(i),
+                0,
+                AppError::InvalidConfig(format!(
+                    "Buffer has incorrect size: expected {}, got {}",
+                    BUFFER_SIZE,
+                    buffer.len()
+                )),
+                report.groups,
+            ));
+        }
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let can_retry = attempts <= options.group_retries;
+
+            if let Err((report_index, error)) = send_group(device, group, options) {
+                if can_retry {
+                    thread::sleep(options.backoff(attempts - 1));
+                    continue;
+                }
+                return Err(fail(Some(report_index), attempts, error, report.groups));
+            }
+
+            let verified = if options.verify {
+                read_back(device, group.kind).map(|frames| frames == group.reports)
+            } else {
+                None
+            };
+
+            if verified == Some(false) {
+                if can_retry {
+                    continue;
+                }
+                return Err(fail(
+                    None,
+                    attempts,
+                    AppError::VerificationFailed,
+                    report.groups,
+                ));
+            }
+
+            report.groups.push(GroupDelivery {
+                kind: group.kind,
+                packets: group.reports.len(),
+                attempts,
+                verified,
+            });
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Send every report of a group, returning the index of the report that failed
+fn send_group(
+    device: &dyn HidConnection,
+    group: &TransferGroup,
+    options: &DeliveryOptions,
+) -> Result<(), (usize, AppError)> {
+    for (i, buffer) in group.reports.iter().enumerate() {
+        if i > 0 && options.inter_packet_delay_ms > 0 {
+            thread::sleep(Duration::from_millis(options.inter_packet_delay_ms));
+        }
+
+        send_with_retry(device, buffer, options).map_err(|e| (i, e))?;
+    }
+
+    Ok(())
+}
+
+/// Send a single report, retrying with backoff
+fn send_with_retry(
+    device: &dyn HidConnection,
+    buffer: &[u8],
+    options: &DeliveryOptions,
+) -> Result<(), AppError> {
+    let mut retry = 0;
+    loop {
+        match device.send_feature_report(buffer) {
+            Ok(()) => return Ok(()),
+            Err(_) if retry < options.report_retries => {
+                thread::sleep(options.backoff(retry));
+                retry += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{device_info, options};
+    use crate::transport::MockDevice;
+    use std::cell::Cell;
+    use std::slice;
+
+    fn device() -> MockDevice {
+        MockDevice::new(device_info("mock"))
+    }
+
+    /// Group of `packets` reports, numbered through their last byte
+    fn group(kind: TransferKind, packets: u8) -> TransferGroup {
+        TransferGroup {
+            kind,
+            reports: (0..packets)
+                .map(|i| {
+                    let mut report = vec![0u8; BUFFER_SIZE];
+                    report[BUFFER_SIZE - 1] = i;
+                    report
+                })
+                .collect(),
+        }
+    }
+
+    fn no_read_back(_: &dyn HidConnection, _: TransferKind) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    #[test]
+    fn failed_report_is_retried_in_place() {
+        let device = device();
+        let group = group(TransferKind::KeyMap, 3);
+        device.fail_next_reports(2);
+
+        let report = deliver(&device, slice::from_ref(&group), &options(), no_read_back).unwrap();
+
+        assert_eq!(report.groups[0].attempts, 1);
+        assert_eq!(device.sent_reports(), group.reports);
+    }
+
+    #[test]
+    fn exhausted_report_retries_resend_the_whole_group() {
+        let device = device();
+        let group = group(TransferKind::KeyMap, 3);
+        device.fail_next_reports(3);
+
+        let report = deliver(&device, slice::from_ref(&group), &options(), no_read_back).unwrap();
+
+        assert_eq!(report.groups[0].attempts, 2);
+        assert_eq!(device.sent_reports(), group.reports);
+    }
+
+    #[test]
+    fn failure_after_all_retries_names_the_group_and_report() {
+        let device = device();
+        let groups = [
+            group(TransferKind::StandardLight, 1),
+            group(TransferKind::KeyMap, 2),
+        ];
+        let options = DeliveryOptions {
+            verify: true,
+            ..options()
+        };
+
+        // The device stops answering once the first group is written
+        let read_backs = Cell::new(0);
+        let error = deliver(&device, &groups, &options, |_, _| {
+            read_backs.set(read_backs.get() + 1);
+            device.fail_next_reports(usize::MAX);
+            None
+        })
+        .unwrap_err();
+
+        assert_eq!(read_backs.get(), 1);
+        assert_eq!(error.group_index, 1);
+        assert_eq!(error.kind, Some(TransferKind::KeyMap));
+        assert_eq!(error.report_index, Some(0));
+        assert_eq!(error.attempts, options.group_retries + 1);
+        assert_eq!(error.code, ErrorCode::DeviceIo);
+        assert_eq!(error.delivered.len(), 1);
+        assert_eq!(error.delivered[0].kind, TransferKind::StandardLight);
+    }
+
+    #[test]
+    fn failure_serializes_like_app_error() {
+        let device = device();
+        device.set_connected(false);
+        let group = group(TransferKind::KeyMap, 2);
+
+        let error =
+            deliver(&device, slice::from_ref(&group), &options(), no_read_back).unwrap_err();
+        let value = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(value["code"], "device_disconnected");
+        assert_eq!(value["message"], error.to_string());
+        let details = &value["details"];
+        assert_eq!(details["group_index"], 0);
+        assert_eq!(
+            details["kind"],
+            serde_json::to_value(TransferKind::KeyMap).unwrap()
+        );
+        assert_eq!(details["report_index"], 0);
+        assert_eq!(details["attempts"], options().group_retries + 1);
+        assert_eq!(details["delivered"], serde_json::json!([]));
+        // Fields of the underlying error are kept
+        assert_eq!(details["path"], "mock");
+        assert!(value.get("group_index").is_none());
+    }
+
+    #[test]
+    fn verification_compares_the_frames_read_back() {
+        let device = device();
+        let group = group(TransferKind::CustomLight, 2);
+        let options = DeliveryOptions {
+            verify: true,
+            ..options()
+        };
+
+        let report = deliver(&device, slice::from_ref(&group), &options, |_, _| {
+            Some(group.reports.clone())
+        })
+        .unwrap();
+        assert_eq!(report.groups[0].verified, Some(true));
+
+        let report = deliver(&device, slice::from_ref(&group), &options, no_read_back).unwrap();
+        assert_eq!(report.groups[0].verified, None);
+    }
+
+    #[test]
+    fn mismatch_is_resent_then_reported() {
+        let device = device();
+        let group = group(TransferKind::CustomLight, 2);
+        let options = DeliveryOptions {
+            verify: true,
+            ..options()
+        };
+
+        // Wrong on the first read only
+        let read_backs = Cell::new(0);
+        let report = deliver(&device, slice::from_ref(&group), &options, |_, _| {
+            read_backs.set(read_backs.get() + 1);
+            if read_backs.get() == 1 {
+                Some(Vec::new())
+            } else {
+                Some(group.reports.clone())
+            }
+        })
+        .unwrap();
+        assert_eq!(report.groups[0].attempts, 2);
+        assert_eq!(report.groups[0].verified, Some(true));
+        assert_eq!(device.sent_reports().len(), 4);
+
+        let error = deliver(&device, &[group], &options, |_, _| Some(Vec::new())).unwrap_err();
+        assert_eq!(error.code, ErrorCode::VerificationFailed);
+        assert_eq!(error.report_index, None);
+        assert_eq!(error.attempts, options.group_retries + 1);
+    }
+
+    #[test]
+    fn malformed_report_is_rejected_before_sending() {
+        let device = device();
+        let mut group = group(TransferKind::KeyMap, 2);
+        group.reports[1].pop();
+
+        let error = deliver(&device, &[group], &options(), no_read_back).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidConfig);
+        assert_eq!(error.report_index, Some(1));
+        assert_eq!(error.attempts, 0);
+        assert!(device.sent_reports().is_empty());
+    }
+
+    #[test]
+    fn backoff_doubles_on_every_retry() {
+        let options = DeliveryOptions::default();
+
+        assert_eq!(options.backoff(0), Duration::from_millis(20));
+        assert_eq!(options.backoff(1), Duration::from_millis(40));
+        assert_eq!(options.backoff(3), Duration::from_millis(160));
+    }
+}
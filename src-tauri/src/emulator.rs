@@ -0,0 +1,500 @@
+use crate::error::AppError;
+use crate::models::{LightModeConfig, RgbColor};
+use crate::protocol::{
+    TransferKind, BUFFER_SIZE, CONTINUATION_DATA_OFFSET, CUSTOM_LIGHT_DATA_OFFSET,
+    KEY_MAP_DATA_OFFSET, READ_REQUEST_FLAG, REPORT_ID,
+};
+use crate::transport::HidConnection;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Protocol violation detected while reassembling a transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// Report is not 65 bytes long
+    InvalidLength(usize),
+    /// Report ID is not 0x0a
+    InvalidReportId(u8),
+    /// First packet carries a command byte the firmware does not know
+    UnknownCommand { sub_command: u8, command: u8 },
+    /// Packet count in the header does not match the command
+    UnexpectedPacketCount {
+        kind: TransferKind,
+        expected: usize,
+        actual: usize,
+    },
+    /// Continuation packet arrived without a transfer in progress
+    UnexpectedContinuation { sequence: u8 },
+    /// Packet sequence number is not the next expected one
+    OutOfOrder { expected: u8, actual: u8 },
+    /// A new transfer started before the previous one was complete
+    Truncated {
+        kind: TransferKind,
+        received: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::InvalidLength(len) => {
+                write!(
+                    f,
+                    "invalid report length: expected {}, got {}",
+                    BUFFER_SIZE, len
+                )
+            }
+            ProtocolError::InvalidReportId(id) => write!(f, "invalid report ID: 0x{:02x}", id),
+            ProtocolError::UnknownCommand {
+                sub_command,
+                command,
+            } => write!(
+                f,
+                "unknown command: 0x{:02x} 0x{:02x}",
+                sub_command, command
+            ),
+            ProtocolError::UnexpectedPacketCount {
+                kind,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?} transfer announces {} packets, expected {}",
+                kind, actual, expected
+            ),
+            ProtocolError::UnexpectedContinuation { sequence } => {
+                write!(
+                    f,
+                    "packet {} received without a transfer in progress",
+                    sequence
+                )
+            }
+            ProtocolError::OutOfOrder { expected, actual } => {
+                write!(
+                    f,
+                    "out-of-order packet: expected {}, got {}",
+                    expected, actual
+                )
+            }
+            ProtocolError::Truncated {
+                kind,
+                received,
+                expected,
+            } => write!(
+                f,
+                "{:?} transfer truncated after {} of {} packets",
+                kind, received, expected
+            ),
+        }
+    }
+}
+
+/// Transfer being reassembled
+struct PendingTransfer {
+    kind: TransferKind,
+    packets: Vec<Vec<u8>>,
+}
+
+/// State of the emulated firmware
+#[derive(Default)]
+struct EmulatorState {
+    pending: Option<PendingTransfer>,
+    light_mode: Option<LightModeConfig>,
+    custom_colors: Option<Vec<RgbColor>>,
+    key_map: Option<Vec<u32>>,
+    errors: Vec<ProtocolError>,
+    completed_transfers: usize,
+    /// Frames of the last complete transfer of each kind, replayed on read requests
+    frames: HashMap<TransferKind, Vec<Vec<u8>>>,
+    read_queue: VecDeque<Vec<u8>>,
+}
+
+/// Software emulation of a Royal Kludge keyboard speaking the 0x0a feature report protocol.
+///
+/// Reports are reassembled into transfers and applied to the emulated lighting and key map
+/// state once complete. Protocol violations are recorded rather than rejected, like the
+/// firmware which silently ignores malformed transfers.
+#[derive(Clone, Default)]
+pub struct EmulatedKeyboard {
+    state: Arc<Mutex<EmulatorState>>,
+}
+
+impl EmulatedKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one 65-byte feature report to the emulator
+    pub fn handle_report(&self, report: &[u8]) -> Result<(), ProtocolError> {
+        let mut state = self.state.lock().unwrap();
+        let result = state.handle_report(report);
+        if let Err(e) = &result {
+            state.errors.push(e.clone());
+        }
+        result
+    }
+
+    /// Last standard light mode applied (custom colors are reported by `custom_colors`)
+    pub fn light_mode(&self) -> Option<LightModeConfig> {
+        self.state.lock().unwrap().light_mode.clone()
+    }
+
+    /// Per-LED colors of the last complete custom light transfer, indexed by buffer index
+    pub fn custom_colors(&self) -> Option<Vec<RgbColor>> {
+        self.state.lock().unwrap().custom_colors.clone()
+    }
+
+    /// Raw key codes of the last complete key mapping transfer, indexed by buffer index
+    pub fn key_map(&self) -> Option<Vec<u32>> {
+        self.state.lock().unwrap().key_map.clone()
+    }
+
+    /// Protocol violations detected so far
+    pub fn errors(&self) -> Vec<ProtocolError> {
+        self.state.lock().unwrap().errors.clone()
+    }
+
+    /// Number of transfers that were fully received and applied
+    pub fn completed_transfers(&self) -> usize {
+        self.state.lock().unwrap().completed_transfers
+    }
+
+    /// Whether a transfer is waiting for more packets
+    pub fn has_pending_transfer(&self) -> bool {
+        self.state.lock().unwrap().pending.is_some()
+    }
+}
+
+impl EmulatorState {
+    fn handle_report(&mut self, report: &[u8]) -> Result<(), ProtocolError> {
+        if report.len() != BUFFER_SIZE {
+            return Err(ProtocolError::InvalidLength(report.len()));
+        }
+        if report[0] != REPORT_ID {
+            return Err(ProtocolError::InvalidReportId(report[0]));
+        }
+
+        let packet_count = report[1] as usize;
+        let sequence = report[2];
+
+        if sequence == 1 {
+            // A new transfer starts; anything still pending was cut short
+            if let Some(pending) = self.pending.take() {
+                self.errors.push(ProtocolError::Truncated {
+                    kind: pending.kind,
+                    received: pending.packets.len(),
+                    expected: pending.kind.packet_count(),
+                });
+            }
+
+            let (sub_command, command) = (report[3], report[4]);
+
+            // Read requests queue the stored frames of the block for `get_feature_report`
+            if sub_command & READ_REQUEST_FLAG != 0 {
+                if let Some(kind) =
+                    TransferKind::from_header(sub_command & !READ_REQUEST_FLAG, command)
+                {
+                    self.read_queue = self.frames.get(&kind).cloned().unwrap_or_default().into();
+                    return Ok(());
+                }
+            }
+
+            let kind = TransferKind::from_header(sub_command, command).ok_or(
+                ProtocolError::UnknownCommand {
+                    sub_command,
+                    command,
+                },
+            )?;
+
+            if packet_count != kind.packet_count() {
+                return Err(ProtocolError::UnexpectedPacketCount {
+                    kind,
+                    expected: kind.packet_count(),
+                    actual: packet_count,
+                });
+            }
+
+            self.pending = Some(PendingTransfer {
+                kind,
+                packets: vec![report.to_vec()],
+            });
+        } else {
+            let pending = self
+                .pending
+                .as_mut()
+                .ok_or(ProtocolError::UnexpectedContinuation { sequence })?;
+
+            let expected = (pending.packets.len() + 1) as u8;
+            if sequence != expected || packet_count != pending.kind.packet_count() {
+                // The transfer cannot be completed anymore
+                self.pending = None;
+                return Err(ProtocolError::OutOfOrder {
+                    expected,
+                    actual: sequence,
+                });
+            }
+
+            pending.packets.push(report.to_vec());
+        }
+
+        let complete = self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.packets.len() == pending.kind.packet_count());
+
+        if complete {
+            if let Some(transfer) = self.pending.take() {
+                self.apply(transfer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a fully received transfer to the emulated state
+    fn apply(&mut self, transfer: PendingTransfer) {
+        match transfer.kind {
+            TransferKind::StandardLight => {
+                let packet = &transfer.packets[0];
+                self.light_mode = Some(LightModeConfig {
+                    mode_bit: packet[5],
+                    animation: packet[7],
+                    brightness: packet[8],
+                    color: Some(RgbColor {
+                        r: packet[9],
+                        g: packet[10],
+                        b: packet[11],
+                    }),
+                    random_colors: packet[12] != 0,
+                    sleep: packet[13],
+                    custom_colors: None,
+                });
+            }
+            TransferKind::CustomLight => {
+                let data = join_payload(&transfer.packets, CUSTOM_LIGHT_DATA_OFFSET);
+                self.custom_colors = Some(
+                    data.chunks_exact(3)
+                        .map(|rgb| RgbColor {
+                            r: rgb[0],
+                            g: rgb[1],
+                            b: rgb[2],
+                        })
+                        .collect(),
+                );
+            }
+            TransferKind::KeyMap => {
+                let data = join_payload(&transfer.packets, KEY_MAP_DATA_OFFSET);
+                self.key_map = Some(
+                    data.chunks_exact(4)
+                        .map(|code| u32::from_be_bytes([code[0], code[1], code[2], code[3]]))
+                        .collect(),
+                );
+            }
+        }
+
+        self.completed_transfers += 1;
+        self.frames.insert(transfer.kind, transfer.packets);
+    }
+}
+
+/// Concatenate the payload of every packet of a transfer
+fn join_payload(packets: &[Vec<u8>], first_offset: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (i, packet) in packets.iter().enumerate() {
+        let start = if i == 0 {
+            first_offset
+        } else {
+            CONTINUATION_DATA_OFFSET
+        };
+        data.extend_from_slice(&packet[start..]);
+    }
+    data
+}
+
+impl HidConnection for EmulatedKeyboard {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), AppError> {
+        // Like the firmware, malformed reports are accepted on the wire and only recorded
+        self.handle_report(data).ok();
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buffer: &mut [u8]) -> Result<usize, AppError> {
+        let report = self
+            .state
+            .lock()
+            .unwrap()
+            .read_queue
+            .pop_front()
+            .ok_or_else(|| {
+                AppError::Protocol("No block requested or block never written".to_string())
+            })?;
+
+        let len = report.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{KEY_MAP_BUFFERS_SIZE, KEY_MAP_COMMAND};
+
+    /// Packet `sequence` of a transfer of `kind`
+    fn packet(kind: TransferKind, sequence: u8) -> Vec<u8> {
+        let mut report = vec![0u8; BUFFER_SIZE];
+        report[0] = REPORT_ID;
+        report[1] = kind.packet_count() as u8;
+        report[2] = sequence;
+        if sequence == 1 {
+            (report[3], report[4]) = kind.header();
+        }
+        report
+    }
+
+    fn send_transfer(emulator: &EmulatedKeyboard, kind: TransferKind) {
+        for sequence in 1..=kind.packet_count() as u8 {
+            emulator.handle_report(&packet(kind, sequence)).unwrap();
+        }
+    }
+
+    #[test]
+    fn complete_transfer_is_applied() {
+        let emulator = EmulatedKeyboard::new();
+        send_transfer(&emulator, TransferKind::KeyMap);
+
+        assert_eq!(emulator.completed_transfers(), 1);
+        assert!(!emulator.has_pending_transfer());
+        assert!(emulator.key_map().is_some());
+        assert!(emulator.errors().is_empty());
+    }
+
+    #[test]
+    fn out_of_order_packet_drops_the_transfer() {
+        let emulator = EmulatedKeyboard::new();
+        emulator
+            .handle_report(&packet(TransferKind::KeyMap, 1))
+            .unwrap();
+        emulator
+            .handle_report(&packet(TransferKind::KeyMap, 2))
+            .unwrap();
+
+        let error = emulator
+            .handle_report(&packet(TransferKind::KeyMap, 4))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ProtocolError::OutOfOrder {
+                expected: 3,
+                actual: 4
+            }
+        );
+        assert!(!emulator.has_pending_transfer());
+
+        // The rest of the transfer has nothing to attach to
+        let error = emulator
+            .handle_report(&packet(TransferKind::KeyMap, 3))
+            .unwrap_err();
+        assert_eq!(error, ProtocolError::UnexpectedContinuation { sequence: 3 });
+        assert_eq!(emulator.completed_transfers(), 0);
+        assert_eq!(emulator.key_map(), None);
+        assert_eq!(emulator.errors().len(), 2);
+    }
+
+    #[test]
+    fn continuation_with_another_packet_count_is_out_of_order() {
+        let emulator = EmulatedKeyboard::new();
+        emulator
+            .handle_report(&packet(TransferKind::KeyMap, 1))
+            .unwrap();
+
+        let mut report = packet(TransferKind::KeyMap, 2);
+        report[1] = TransferKind::CustomLight.packet_count() as u8;
+        assert_eq!(
+            emulator.handle_report(&report),
+            Err(ProtocolError::OutOfOrder {
+                expected: 2,
+                actual: 2
+            })
+        );
+    }
+
+    #[test]
+    fn new_transfer_truncates_the_pending_one() {
+        let emulator = EmulatedKeyboard::new();
+        for sequence in 1..=3 {
+            emulator
+                .handle_report(&packet(TransferKind::KeyMap, sequence))
+                .unwrap();
+        }
+
+        // The interrupting transfer itself is accepted
+        send_transfer(&emulator, TransferKind::StandardLight);
+
+        assert_eq!(
+            emulator.errors(),
+            [ProtocolError::Truncated {
+                kind: TransferKind::KeyMap,
+                received: 3,
+                expected: KEY_MAP_BUFFERS_SIZE,
+            }]
+        );
+        assert_eq!(emulator.completed_transfers(), 1);
+        assert!(emulator.light_mode().is_some());
+        assert_eq!(emulator.key_map(), None);
+    }
+
+    #[test]
+    fn continuation_without_transfer_is_unexpected() {
+        let emulator = EmulatedKeyboard::new();
+
+        assert_eq!(
+            emulator.handle_report(&packet(TransferKind::CustomLight, 2)),
+            Err(ProtocolError::UnexpectedContinuation { sequence: 2 })
+        );
+        assert!(!emulator.has_pending_transfer());
+    }
+
+    #[test]
+    fn malformed_first_packets_are_rejected() {
+        let emulator = EmulatedKeyboard::new();
+
+        assert_eq!(
+            emulator.handle_report(&[REPORT_ID; 64]),
+            Err(ProtocolError::InvalidLength(64))
+        );
+
+        let mut report = packet(TransferKind::StandardLight, 1);
+        report[0] = 0x06;
+        assert_eq!(
+            emulator.handle_report(&report),
+            Err(ProtocolError::InvalidReportId(0x06))
+        );
+
+        let mut report = packet(TransferKind::KeyMap, 1);
+        report[3] = 0x05;
+        assert_eq!(
+            emulator.handle_report(&report),
+            Err(ProtocolError::UnknownCommand {
+                sub_command: 0x05,
+                command: KEY_MAP_COMMAND
+            })
+        );
+
+        let mut report = packet(TransferKind::KeyMap, 1);
+        report[1] = 3;
+        assert_eq!(
+            emulator.handle_report(&report),
+            Err(ProtocolError::UnexpectedPacketCount {
+                kind: TransferKind::KeyMap,
+                expected: KEY_MAP_BUFFERS_SIZE,
+                actual: 3
+            })
+        );
+
+        assert_eq!(emulator.errors().len(), 4);
+        assert_eq!(emulator.completed_transfers(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// Stable identifier of an error kind, for the frontend to branch on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    HidUnavailable,
+    PermissionDenied,
+    DeviceNotFound,
+    DeviceDisconnected,
+    DeviceIo,
+    Timeout,
+    Cancelled,
+    DefinitionMissing,
+    DefinitionInvalid,
+    UnsupportedKeyboard,
+    InvalidConfig,
+    UnsupportedFirmware,
+    VerificationFailed,
+    Protocol,
+    Internal,
+}
+
+impl ErrorCode {
+    /// Whether the failure comes from the link to the device (e.g. a stale handle after
+    /// a replug) rather than from the data sent, so reopening the device may help
+    pub fn is_connection(self) -> bool {
+        matches!(self, ErrorCode::DeviceIo | ErrorCode::DeviceDisconnected)
+    }
+}
+
+/// Error returned by the HID, protocol and keyboard definition layers.
+///
+/// Serialized to the frontend as `{ code, message, details }`, where `code` is an
+/// `ErrorCode` and `details` carries the variant fields (path, VID/PID, ...) or `null`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AppError {
+    #[error("Failed to initialize HID API: {0}")]
+    HidUnavailable(String),
+    #[error("Permission denied opening device at {path}: {message}")]
+    PermissionDenied { path: String, message: String },
+    #[error("No device at {path}")]
+    DeviceNotFound { path: String },
+    #[error("No connected keyboard with VID 0x{vid:04x} and PID 0x{pid:04x}")]
+    NoMatchingDevice { vid: u16, pid: u16 },
+    #[error("Device at {path} is disconnected")]
+    DeviceDisconnected { path: String },
+    #[error("{0}")]
+    DeviceIo(String),
+    #[error("Device did not respond within {ms} ms")]
+    Timeout { ms: u64 },
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("Config file not found: {path}")]
+    DefinitionMissing { path: String },
+    #[error("Invalid config file {path}: {message}")]
+    DefinitionInvalid { path: String, message: String },
+    #[error("Keyboard 0x{vid:04x}:0x{pid:04x} is disabled in config")]
+    KeyboardDisabled { vid: u16, pid: u16 },
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Firmware does not support reading the configuration: {0}")]
+    UnsupportedFirmware(String),
+    #[error("Read-back does not match the sent data")]
+    VerificationFailed,
+    #[error("{0}")]
+    Protocol(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::HidUnavailable(_) => ErrorCode::HidUnavailable,
+            AppError::PermissionDenied { .. } => ErrorCode::PermissionDenied,
+            AppError::DeviceNotFound { .. } | AppError::NoMatchingDevice { .. } => {
+                ErrorCode::DeviceNotFound
+            }
+            AppError::DeviceDisconnected { .. } => ErrorCode::DeviceDisconnected,
+            AppError::DeviceIo(_) => ErrorCode::DeviceIo,
+            AppError::Timeout { .. } => ErrorCode::Timeout,
+            AppError::Cancelled => ErrorCode::Cancelled,
+            AppError::DefinitionMissing { .. } => ErrorCode::DefinitionMissing,
+            AppError::DefinitionInvalid { .. } => ErrorCode::DefinitionInvalid,
+            AppError::KeyboardDisabled { .. } => ErrorCode::UnsupportedKeyboard,
+            AppError::InvalidConfig(_) => ErrorCode::InvalidConfig,
+            AppError::UnsupportedFirmware(_) => ErrorCode::UnsupportedFirmware,
+            AppError::VerificationFailed => ErrorCode::VerificationFailed,
+            AppError::Protocol(_) => ErrorCode::Protocol,
+            AppError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Structured fields of the error, for the frontend to show or act on
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            AppError::PermissionDenied { path, .. }
+            | AppError::DeviceNotFound { path }
+            | AppError::DeviceDisconnected { path }
+            | AppError::DefinitionMissing { path }
+            | AppError::DefinitionInvalid { path, .. } => Some(json!({ "path": path })),
+            AppError::NoMatchingDevice { vid, pid } | AppError::KeyboardDisabled { vid, pid } => {
+                Some(json!({ "vid": vid, "pid": pid }))
+            }
+            AppError::Timeout { ms } => Some(json!({ "timeout_ms": ms })),
+            _ => None,
+        }
+    }
+
+    /// Classify a platform HID error message, which is all hidapi reports
+    pub fn from_hid_message(path: &str, message: String) -> Self {
+        let lower = message.to_lowercase();
+
+        if lower.contains("permission denied")
+            || lower.contains("access is denied")
+            || lower.contains("not permitted")
+        {
+            AppError::PermissionDenied {
+                path: path.to_string(),
+                message,
+            }
+        } else if lower.contains("no such device")
+            || lower.contains("not connected")
+            || lower.contains("device not configured")
+        {
+            AppError::DeviceDisconnected {
+                path: path.to_string(),
+            }
+        } else {
+            AppError::DeviceIo(message)
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
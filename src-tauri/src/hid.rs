@@ -1,56 +1,144 @@
-use crate::keyboard::{get_resource_dir, load_keyboard_config, matches_unix_device};
-use crate::models::Keyboard;
-use hidapi::HidApi;
-use std::sync::{Arc, Mutex};
+use crate::decoder::decode_buffers;
+use crate::delivery::{DeliveryError, DeliveryOptions, DeliveryReport};
+use crate::error::AppError;
+use crate::keyboard::{matches_unix_device, DefinitionRoots};
+use crate::models::{Keyboard, KeyboardConfig, KeyboardUsbId};
+use crate::modes::is_custom_mode;
+use crate::protocol::{
+    build_read_request, build_transfer_groups, TransferGroup, TransferKind, BUFFER_SIZE, REPORT_ID,
+};
+use crate::registry::{
+    definition_file_id, definition_stamps, DefinitionChange, DefinitionRegistry, SupportedKeyboards,
+};
+use crate::transport::{HidConnection, HidTransport, HidapiTransport};
+use crate::validation::validate_config;
+use crate::worker::{DeviceWorker, SharedTransport};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Runtime};
 
 const SUPPORTED_VID: u16 = 0x258a;
-const BUFFER_SIZE: usize = 65;
+
+/// Event emitted when a supported keyboard is plugged in
+pub const KEYBOARD_CONNECTED_EVENT: &str = "keyboard-connected";
+/// Event emitted when a previously seen keyboard is unplugged
+pub const KEYBOARD_DISCONNECTED_EVENT: &str = "keyboard-disconnected";
+/// Event emitted when the files of a keyboard definition change on disk
+pub const KEYBOARD_DEFINITION_CHANGED_EVENT: &str = "keyboard-definition-changed";
+/// Default interval between two enumerations of the device watcher
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Outcome of sending a configuration to one physical unit
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceSendResult {
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub error: Option<DeliveryError>,
+}
 
 /// HID manager for device enumeration and communication
 pub struct HidManager {
-    api: Arc<Mutex<HidApi>>,
+    transport: SharedTransport,
+    /// One long-lived worker per device path, created on first use
+    workers: Mutex<HashMap<String, Arc<DeviceWorker>>>,
+    /// Keyboard definitions, loaded once instead of on every scan
+    definitions: Mutex<DefinitionRegistry>,
 }
 
 impl HidManager {
-    /// Initialize HID API
-    pub fn new() -> Result<Self, String> {
-        let api = HidApi::new().map_err(|e| format!("Failed to initialize HID API: {}", e))?;
+    /// Initialize HID manager backed by the system HID API, with the definitions of
+    /// every definitions root
+    pub fn new() -> Result<Self, AppError> {
+        Ok(Self::with_transport(
+            Box::new(HidapiTransport::new()?),
+            DefinitionRegistry::load(DefinitionRoots::discover()),
+        ))
+    }
+
+    /// Create a HID manager on top of an arbitrary transport (e.g. `MockTransport`)
+    pub fn with_transport(
+        transport: Box<dyn HidTransport>,
+        definitions: DefinitionRegistry,
+    ) -> Self {
+        Self {
+            transport: Arc::new(Mutex::new(transport)),
+            workers: Mutex::new(HashMap::new()),
+            definitions: Mutex::new(definitions),
+        }
+    }
 
-        Ok(Self {
-            api: Arc::new(Mutex::new(api)),
-        })
+    /// Every model with a definition, whether connected or not
+    pub fn supported_keyboards(&self) -> SupportedKeyboards {
+        lock_or_recover(&self.definitions).supported_keyboards()
+    }
+
+    /// Directories the definitions are loaded from
+    pub fn definition_roots(&self) -> DefinitionRoots {
+        lock_or_recover(&self.definitions).roots().clone()
+    }
+
+    /// Look definitions up in `roots` from now on
+    pub fn set_definition_roots(&self, roots: DefinitionRoots) {
+        lock_or_recover(&self.definitions).set_roots(roots);
+    }
+
+    /// Re-read the definition of one model after its files changed
+    pub fn reload_definition(&self, id: KeyboardUsbId) -> DefinitionChange {
+        lock_or_recover(&self.definitions).reload(id)
+    }
+
+    /// Re-check the definition files that are not named after a VID/PID
+    pub fn reload_misnamed_definitions(&self) {
+        lock_or_recover(&self.definitions).reload_misnamed();
     }
 
     /// Scan for connected keyboards
-    pub fn scan_keyboards(&self) -> Result<Vec<Keyboard>, String> {
-        // Refresh HID API to detect newly connected devices
-        let mut api_guard = self.api.lock().unwrap();
-        *api_guard = HidApi::new()
-            .map_err(|e| format!("Failed to refresh HID API: {}", e))?;
-        let api = api_guard;
-        
-        let resource_dir = get_resource_dir();
-        let devices = api.device_list();
-        
+    pub fn scan_keyboards(&self) -> Result<Vec<Keyboard>, AppError> {
+        // Refresh the device list to detect newly connected devices
+        let devices = lock_or_recover(&self.transport).enumerate()?;
+
+        // Stop the workers of devices that went away
+        let stale_workers: Vec<Arc<DeviceWorker>> = {
+            let mut workers = lock_or_recover(&self.workers);
+            let stale_paths: Vec<String> = workers
+                .keys()
+                .filter(|path| !devices.iter().any(|device| &device.path == *path))
+                .cloned()
+                .collect();
+            stale_paths
+                .iter()
+                .filter_map(|path| workers.remove(path))
+                .collect()
+        };
+        drop(stale_workers);
+
+        let definitions = lock_or_recover(&self.definitions);
+
         let mut keyboards = Vec::new();
-        let mut seen_ids = std::collections::HashSet::new();
+        let mut seen_units = std::collections::HashSet::new();
 
         for device_info in devices {
-            let vid = device_info.vendor_id();
-            let pid = device_info.product_id();
+            let vid = device_info.vendor_id;
+            let pid = device_info.product_id;
 
             // Only process devices with supported VID
             if vid != SUPPORTED_VID {
                 continue;
             }
 
-            // Skip if we've already processed this VID/PID combination
-            let usb_id = (vid, pid);
-            if seen_ids.contains(&usb_id) {
+            // Skip other interfaces of a physical unit we've already processed,
+            // while keeping identical models connected side by side
+            let unit_id = (vid, pid, physical_unit_id(&device_info.path));
+            if seen_units.contains(&unit_id) {
                 continue;
             }
 
-            let device_path = device_info.path().to_string_lossy().to_string();
+            let device_path = device_info.path.clone();
 
             // Platform-specific filtering
             #[cfg(windows)]
@@ -66,58 +154,270 @@ impl HidManager {
             #[cfg(not(windows))]
             {
                 // On Linux/Mac, check usage page and usage
-                let usage_page = device_info.usage_page();
-                let usage = device_info.usage();
-                if !matches_unix_device(usage_page, usage) {
+                if !matches_unix_device(device_info.usage_page, device_info.usage) {
                     continue;
                 }
             }
 
-            // Try to load keyboard config
-            match load_keyboard_config(vid, pid, &device_path, &resource_dir) {
-                Ok(keyboard) => {
-                    seen_ids.insert(usb_id);
-                    keyboards.push(keyboard);
-                }
-                Err(_) => {
-                    // Device might not be supported, continue
-                }
+            // Models without a usable definition are not supported
+            if let Some(mut keyboard) =
+                definitions.keyboard(KeyboardUsbId { vid, pid }, &device_path)
+            {
+                keyboard.serial_number = device_info.serial_number.clone();
+                keyboard.interface_number = device_info.interface_number;
+                seen_units.insert(unit_id);
+                keyboards.push(keyboard);
             }
         }
 
         Ok(keyboards)
     }
 
-    /// Send feature report buffers to keyboard
-    pub fn send_buffers(&self, device_path: &str, buffers: &[Vec<u8>]) -> Result<(), String> {
-        let api = self.api.lock().unwrap();
+    /// Send transfer groups to the keyboard with retries, pacing and optional verification
+    pub fn send_groups(
+        &self,
+        device_path: &str,
+        groups: &[TransferGroup],
+        options: &DeliveryOptions,
+    ) -> Result<DeliveryReport, DeliveryError> {
+        self.with_worker(device_path, |worker| worker.send(groups.to_vec(), *options))
+    }
 
-        // Convert string path to CString for hidapi
-        use std::ffi::CString;
-        let c_path =
-            CString::new(device_path).map_err(|e| format!("Invalid device path: {}", e))?;
+    /// Run `operation` on the worker of the given device, starting it if needed.
+    ///
+    /// The worker map is only locked while looking the worker up, so requests for
+    /// the same device can queue up (and be coalesced) while one is in flight.
+    /// A worker whose thread died (e.g. after a panic) is replaced.
+    fn with_worker<T>(&self, device_path: &str, operation: impl FnOnce(&DeviceWorker) -> T) -> T {
+        let worker = {
+            let mut workers = lock_or_recover(&self.workers);
+            let worker = workers
+                .entry(device_path.to_string())
+                .and_modify(|worker| {
+                    if !worker.is_running() {
+                        *worker = self.spawn_worker(device_path);
+                    }
+                })
+                .or_insert_with(|| self.spawn_worker(device_path));
+            worker.clone()
+        };
 
-        let device = api
-            .open_path(c_path.as_c_str())
-            .map_err(|e| format!("Failed to open device at {}: {}", device_path, e))?;
+        operation(&worker)
+    }
 
-        for (i, buffer) in buffers.iter().enumerate() {
-            if buffer.len() != BUFFER_SIZE {
-                return Err(format!(
-                    "Buffer {} has incorrect size: expected {}, got {}",
-                    i,
-                    BUFFER_SIZE,
-                    buffer.len()
-                ));
-            }
+    fn spawn_worker(&self, device_path: &str) -> Arc<DeviceWorker> {
+        Arc::new(DeviceWorker::spawn(
+            device_path.to_string(),
+            self.transport.clone(),
+        ))
+    }
+
+    /// Drop the requests queued for a device that have not started yet
+    pub fn cancel_pending(&self, device_path: &str) {
+        if let Some(worker) = lock_or_recover(&self.workers).get(device_path) {
+            worker.cancel();
+        }
+    }
+
+    /// Build the protocol buffers for a configuration and send them to the keyboard
+    pub fn send_config(
+        &self,
+        device_path: &str,
+        keyboard: &Keyboard,
+        config: &KeyboardConfig,
+        options: &DeliveryOptions,
+    ) -> Result<DeliveryReport, DeliveryError> {
+        // Parts outside the scope are left out before they can fail validation or encoding
+        let config = options.scope.select(config);
+        let (config, mut warnings) = validate_config(keyboard, &config, options.validation)
+            .map_err(DeliveryError::before_send)?;
+
+        // Verification reads the blocks back, which only declared models are asked to do
+        let mut options = *options;
+        if options.verify && !keyboard.read_back {
+            options.verify = false;
+            warnings.push(format!(
+                "{} cannot read its configuration back, the transfer is not verified",
+                keyboard.name
+            ));
+        }
+
+        for warning in &warnings {
+            eprintln!("[HidManager] {}", warning);
+        }
+
+        // Build protocol buffers of the requested part
+        let groups: Vec<TransferGroup> = build_transfer_groups(keyboard, &config)
+            .map_err(DeliveryError::before_send)?
+            .into_iter()
+            .filter(|group| options.scope.includes(group.kind))
+            .collect();
 
-            device
-                .send_feature_report(buffer)
-                .map_err(|e| format!("Failed to send feature report {}: {}", i, e))?;
+        // Send buffers to device
+        let mut report = self.send_groups(device_path, &groups, &options)?;
+        report.warnings = warnings;
+        Ok(report)
+    }
+
+    /// Send a configuration to every connected unit of the same model as `keyboard`
+    pub fn send_config_to_all(
+        &self,
+        keyboard: &Keyboard,
+        config: &KeyboardConfig,
+        options: &DeliveryOptions,
+    ) -> Result<Vec<DeviceSendResult>, AppError> {
+        let units: Vec<Keyboard> = self
+            .scan_keyboards()?
+            .into_iter()
+            .filter(|unit| unit.id == keyboard.id)
+            .collect();
+
+        if units.is_empty() {
+            return Err(AppError::NoMatchingDevice {
+                vid: keyboard.id.vid,
+                pid: keyboard.id.pid,
+            });
         }
 
-        Ok(())
+        Ok(units
+            .into_iter()
+            .map(|unit| DeviceSendResult {
+                error: self.send_config(&unit.path, &unit, config, options).err(),
+                path: unit.path,
+                serial_number: unit.serial_number,
+            })
+            .collect())
     }
+
+    /// Read the configuration currently stored on the keyboard.
+    ///
+    /// The lighting block is required; the per-key color and key map blocks are
+    /// skipped when the firmware does not answer them. Not exposed as a command until
+    /// the read request is confirmed on a board (see `build_read_request`).
+    pub fn read_keyboard_config(
+        &self,
+        device_path: &str,
+        keyboard: &Keyboard,
+    ) -> Result<KeyboardConfig, AppError> {
+        self.with_worker(device_path, |worker| worker.read(keyboard.clone()))
+    }
+}
+
+/// Lock a mutex, recovering the data if a previous holder panicked.
+///
+/// The guarded state (transport, worker map) stays consistent across a panic, so
+/// one failed operation must not make the HID stack unusable for the rest of the session.
+pub(crate) fn lock_or_recover<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Read the lighting and key map blocks from an open device and decode them.
+///
+/// Only models whose definition declares `readBack` are asked, since the read request
+/// is unverified and other firmware may take it for a write.
+pub(crate) fn read_config(
+    device: &dyn HidConnection,
+    keyboard: &Keyboard,
+) -> Result<KeyboardConfig, AppError> {
+    if !keyboard.read_back {
+        return Err(AppError::UnsupportedFirmware(format!(
+            "{} does not declare support for reading its configuration",
+            keyboard.name
+        )));
+    }
+
+    let mut reports = read_block(device, TransferKind::StandardLight).map_err(|e| match e {
+        AppError::Protocol(message) => AppError::UnsupportedFirmware(message),
+        e => e,
+    })?;
+
+    let mut optional_blocks = Vec::new();
+    if is_custom_mode(reports[0][5], keyboard.rgb) {
+        optional_blocks.push(TransferKind::CustomLight);
+    }
+    if keyboard.key_map_enabled {
+        optional_blocks.push(TransferKind::KeyMap);
+    }
+
+    for kind in optional_blocks {
+        match read_block(device, kind) {
+            Ok(block) => reports.extend(block),
+            Err(e) => eprintln!("[HidManager] Skipping {:?} block: {}", kind, e),
+        }
+    }
+
+    let decoded = decode_buffers(keyboard, &reports);
+    if !decoded.errors.is_empty() {
+        return Err(AppError::Protocol(format!(
+            "Failed to decode configuration: {}",
+            decoded.errors.join(", ")
+        )));
+    }
+
+    Ok(decoded.config)
+}
+
+/// Identify the physical unit a HID interface belongs to, from its path.
+///
+/// Interfaces of one unit only differ by a suffix: the top-level collection on
+/// Windows, where every collection gets its own path, and the interface number in
+/// libusb paths (`<bus>-<port>:<config>.<interface>`). The serial number is not
+/// used, since identical boards often report the same one.
+fn physical_unit_id(path: &str) -> String {
+    // e.g. \\?\HID#VID_258A&PID_0049&MI_01&Col01#7&2a3f1c&0&0000#{...}
+    let mut segments: Vec<String> = path.split('#').map(str::to_string).collect();
+    if segments.len() > 2 {
+        if let Some(col) = segments[1].to_ascii_lowercase().find("&col") {
+            segments[1].truncate(col);
+        }
+        if let Some(collection_index) = segments[2].rfind('&') {
+            segments[2].truncate(collection_index);
+        }
+        return segments.join("#");
+    }
+
+    // e.g. 1-2.3:1.0
+    match path.rsplit_once(':') {
+        Some((port, interface)) if interface.contains('.') => {
+            let config = interface.split('.').next().unwrap_or_default();
+            format!("{}:{}", port, config)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Request a block from the firmware and read back all of its packets
+pub(crate) fn read_block(
+    device: &dyn HidConnection,
+    kind: TransferKind,
+) -> Result<Vec<Vec<u8>>, AppError> {
+    device.send_feature_report(&build_read_request(kind))?;
+
+    let mut reports = Vec::with_capacity(kind.packet_count());
+    for i in 0..kind.packet_count() {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        buffer[0] = REPORT_ID;
+
+        let len = device.get_feature_report(&mut buffer)?;
+
+        // Unsupported firmware answers with an empty or unrelated report, or echoes
+        // the request back
+        if len != BUFFER_SIZE
+            || buffer[0] != REPORT_ID
+            || buffer[1] as usize != kind.packet_count()
+            || buffer[2] as usize != i + 1
+            || (i == 0 && (buffer[3], buffer[4]) != kind.header())
+        {
+            return Err(AppError::Protocol(format!(
+                "Unexpected response to {:?} read request",
+                kind
+            )));
+        }
+
+        reports.push(buffer);
+    }
+
+    Ok(reports)
 }
 
 impl Default for HidManager {
@@ -125,3 +425,551 @@ impl Default for HidManager {
         Self::new().expect("Failed to initialize HID manager")
     }
 }
+
+/// Background thread that periodically enumerates devices and emits
+/// `keyboard-connected` / `keyboard-disconnected` events with the `Keyboard` model
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    /// Start watching for attached and detached keyboards
+    pub fn spawn<R: Runtime>(
+        app: AppHandle<R>,
+        hid_manager: Arc<HidManager>,
+        interval: Duration,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let handle = thread::spawn(move || {
+            // Keyboards present at startup are picked up by the frontend's initial scan,
+            // so only changes relative to this snapshot are reported
+            let mut known = index_by_path(hid_manager.scan_keyboards().unwrap_or_default());
+
+            while !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+
+                let current = match hid_manager.scan_keyboards() {
+                    Ok(keyboards) => keyboards,
+                    Err(e) => {
+                        eprintln!("[DeviceWatcher] Scan failed: {}", e);
+                        continue;
+                    }
+                };
+
+                let (connected, disconnected) = diff_keyboards(&known, &current);
+
+                for keyboard in disconnected {
+                    app.emit(KEYBOARD_DISCONNECTED_EVENT, keyboard).ok();
+                }
+                for keyboard in connected {
+                    app.emit(KEYBOARD_CONNECTED_EVENT, keyboard).ok();
+                }
+
+                known = index_by_path(current);
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the watcher thread and wait for it to exit
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Environment variable that turns the definition watcher on in release builds
+pub const WATCH_DEFINITIONS_ENV: &str = "RK_WATCH_DEFINITIONS";
+
+/// Whether to run a `DefinitionWatcher`: always in debug builds, and in release builds
+/// only when `RK_WATCH_DEFINITIONS` is set
+pub fn watch_definitions() -> bool {
+    cfg!(debug_assertions)
+        || std::env::var_os(WATCH_DEFINITIONS_ENV).is_some_and(|value| !value.is_empty())
+}
+
+/// Background thread that polls the definitions roots (looking the override directories
+/// up again each time) and, when a definition file or image changes, reloads it and
+/// emits `keyboard-definition-changed` with the new layout
+pub struct DefinitionWatcher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DefinitionWatcher {
+    /// Start watching the definitions of `hid_manager`
+    pub fn spawn<R: Runtime>(
+        app: AppHandle<R>,
+        hid_manager: Arc<HidManager>,
+        interval: Duration,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let mut known = definition_stamps(&hid_manager.definition_roots());
+
+            while !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(interval);
+
+                // Override directories may have been created or removed since the last poll
+                let roots = hid_manager.definition_roots().rediscover();
+                for change in poll_definitions(&hid_manager, roots, &mut known) {
+                    eprintln!(
+                        "[DefinitionWatcher] Reloaded definition of {:04x}:{:04x}",
+                        change.id.vid, change.id.pid
+                    );
+                    app.emit(KEYBOARD_DEFINITION_CHANGED_EVENT, change).ok();
+                }
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the watcher thread and wait for it to exit
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+impl Drop for DefinitionWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Switch `hid_manager` to `roots` and reload the models whose files were added,
+/// modified or removed since `known` was taken, updating `known`.
+///
+/// Files of a root that appeared or went away show up as added or removed, so the
+/// models they define move between the override and the bundled definition.
+fn poll_definitions(
+    hid_manager: &HidManager,
+    roots: DefinitionRoots,
+    known: &mut HashMap<PathBuf, SystemTime>,
+) -> Vec<DefinitionChange> {
+    let current = definition_stamps(&roots);
+    hid_manager.set_definition_roots(roots);
+
+    // Added or modified files, then removed ones
+    let changed = current
+        .iter()
+        .filter(|(path, modified)| known.get(*path) != Some(*modified))
+        .map(|(path, _)| path)
+        .chain(known.keys().filter(|path| !current.contains_key(*path)));
+
+    let mut ids = HashSet::new();
+    let mut misnamed = false;
+    for path in changed {
+        match definition_file_id(path) {
+            Some(id) => {
+                ids.insert(id);
+            }
+            None => misnamed = true,
+        }
+    }
+
+    if misnamed {
+        hid_manager.reload_misnamed_definitions();
+    }
+    let mut ids: Vec<KeyboardUsbId> = ids.into_iter().collect();
+    ids.sort_by_key(|id| (id.vid, id.pid));
+
+    *known = current;
+    ids.into_iter()
+        .map(|id| hid_manager.reload_definition(id))
+        .collect()
+}
+
+/// Index keyboards by device path, which is unique per connected interface
+fn index_by_path(keyboards: Vec<Keyboard>) -> HashMap<String, Keyboard> {
+    keyboards
+        .into_iter()
+        .map(|keyboard| (keyboard.path.clone(), keyboard))
+        .collect()
+}
+
+/// Compare two enumerations and return the (connected, disconnected) keyboards
+fn diff_keyboards(
+    previous: &HashMap<String, Keyboard>,
+    current: &[Keyboard],
+) -> (Vec<Keyboard>, Vec<Keyboard>) {
+    let connected = current
+        .iter()
+        .filter(|keyboard| !previous.contains_key(&keyboard.path))
+        .cloned()
+        .collect();
+
+    let disconnected = previous
+        .values()
+        .filter(|keyboard| !current.iter().any(|k| k.path == keyboard.path))
+        .cloned()
+        .collect();
+
+    (connected, disconnected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::UpdateScope;
+    use crate::emulator::EmulatedKeyboard;
+    use crate::keyboard::definition_relative_path;
+    use crate::models::{
+        KeyCode, KeyLayer, KeyMapping, KeyMappingConfig, LightModeConfig, PerKeyColor, RgbColor,
+    };
+    use crate::protocol::build_buffers;
+    use crate::test_support::{self, bundled_roots, device_info, manager, TempRoot};
+    use crate::transport::{DeviceInfo, MockDevice, MockTransport};
+    use std::fs;
+
+    fn keyboard(path: &str) -> Keyboard {
+        Keyboard {
+            path: path.to_string(),
+            interface_number: 1,
+            ..test_support::keyboard(Vec::new())
+        }
+    }
+
+    fn lighting(mode_bit: u8) -> KeyboardConfig {
+        KeyboardConfig {
+            light_mode: Some(LightModeConfig {
+                mode_bit,
+                animation: 2,
+                brightness: 3,
+                color: Some(RgbColor { r: 9, g: 8, b: 7 }),
+                random_colors: false,
+                sleep: 5,
+                custom_colors: None,
+            }),
+            key_mapping: None,
+            macros: None,
+        }
+    }
+
+    fn paths(keyboards: &[Keyboard]) -> Vec<&str> {
+        let mut paths: Vec<&str> = keyboards.iter().map(|k| k.path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn diff_reports_attached_and_detached_keyboards() {
+        let previous = index_by_path(vec![keyboard("a"), keyboard("b")]);
+        let current = vec![keyboard("b"), keyboard("c")];
+
+        let (connected, disconnected) = diff_keyboards(&previous, &current);
+
+        assert_eq!(paths(&connected), ["c"]);
+        assert_eq!(paths(&disconnected), ["a"]);
+    }
+
+    #[test]
+    fn diff_of_unchanged_enumeration_is_empty() {
+        let previous = index_by_path(vec![keyboard("a"), keyboard("b")]);
+        let current = vec![keyboard("b"), keyboard("a")];
+
+        let (connected, disconnected) = diff_keyboards(&previous, &current);
+
+        assert!(connected.is_empty());
+        assert!(disconnected.is_empty());
+    }
+
+    #[test]
+    fn replugged_keyboard_on_new_path_is_reported_twice() {
+        let previous = index_by_path(vec![keyboard("usb-1")]);
+        let current = vec![keyboard("usb-2")];
+
+        let (connected, disconnected) = diff_keyboards(&previous, &current);
+
+        assert_eq!(paths(&connected), ["usb-2"]);
+        assert_eq!(paths(&disconnected), ["usb-1"]);
+    }
+
+    #[test]
+    fn read_config_returns_the_blocks_written() {
+        let emulator = EmulatedKeyboard::new();
+        let keyboard = Keyboard {
+            read_back: true,
+            ..keyboard("a")
+        };
+        for report in build_buffers(&keyboard, &lighting(16)).unwrap() {
+            emulator.send_feature_report(&report).unwrap();
+        }
+
+        // The key map was never written, so only the lighting block answers
+        let config = read_config(&emulator, &keyboard).unwrap();
+
+        let light = config.light_mode.unwrap();
+        assert_eq!(
+            (
+                light.mode_bit,
+                light.animation,
+                light.brightness,
+                light.sleep
+            ),
+            (16, 2, 3, 5)
+        );
+        assert!(config.key_mapping.is_none());
+    }
+
+    #[test]
+    fn read_config_is_not_sent_to_undeclared_models() {
+        let device = MockDevice::with_emulator(device_info("a"), EmulatedKeyboard::new());
+
+        let error = read_config(&device, &keyboard("a")).unwrap_err();
+
+        assert!(matches!(error, AppError::UnsupportedFirmware(_)));
+        assert!(device.sent_reports().is_empty());
+    }
+
+    #[test]
+    fn read_block_rejects_an_echoed_request() {
+        let device = MockDevice::new(device_info("a"));
+        device.push_response(build_read_request(TransferKind::StandardLight));
+
+        let error = read_block(&device, TransferKind::StandardLight).unwrap_err();
+
+        assert!(matches!(error, AppError::Protocol(_)));
+    }
+
+    #[test]
+    fn verification_is_skipped_for_undeclared_models() {
+        let transport = MockTransport::new();
+        let emulator = EmulatedKeyboard::new();
+        transport.add_device(MockDevice::with_emulator(
+            device_info("a"),
+            emulator.clone(),
+        ));
+        let manager = manager(&transport);
+        let options = DeliveryOptions {
+            verify: true,
+            ..Default::default()
+        };
+
+        let report = manager
+            .send_config("a", &keyboard("a"), &lighting(16), &options)
+            .unwrap();
+
+        assert_eq!(report.groups[0].verified, None);
+        assert_eq!(report.warnings.len(), 1);
+        // Only the write reached the device, no read request
+        assert_eq!(transport.device("a").unwrap().sent_reports().len(), 1);
+        assert_eq!(emulator.completed_transfers(), 1);
+    }
+
+    #[test]
+    fn scoped_update_ignores_errors_in_the_parts_it_does_not_send() {
+        let transport = MockTransport::new();
+        transport.add_device(MockDevice::new(device_info("a")));
+        let manager = manager(&transport);
+
+        // Fn layer mappings cannot be written, and index 200 is beyond the custom light block
+        let mut config = lighting(16);
+        config.key_mapping = Some(KeyMappingConfig {
+            mappings: vec![KeyMapping {
+                buffer_index: 0,
+                key_code: KeyCode::KeyA,
+                layer: KeyLayer::Fn,
+            }],
+        });
+        if let Some(light_mode) = config.light_mode.as_mut() {
+            light_mode.custom_colors = Some(vec![PerKeyColor {
+                buffer_index: 200,
+                color: RgbColor { r: 1, g: 2, b: 3 },
+            }]);
+        }
+        let scoped = |scope| DeliveryOptions {
+            scope,
+            ..Default::default()
+        };
+
+        let report = manager
+            .send_config("a", &keyboard("a"), &config, &scoped(UpdateScope::Lighting))
+            .unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].kind, TransferKind::StandardLight);
+
+        for scope in [
+            UpdateScope::All,
+            UpdateScope::KeyMap,
+            UpdateScope::CustomColors,
+        ] {
+            let error = manager
+                .send_config("a", &keyboard("a"), &config, &scoped(scope))
+                .unwrap_err();
+            assert_eq!(error.code, crate::error::ErrorCode::InvalidConfig);
+        }
+        assert_eq!(transport.device("a").unwrap().sent_reports().len(), 1);
+    }
+
+    #[test]
+    fn verification_reads_declared_models_back() {
+        let transport = MockTransport::new();
+        transport.add_device(MockDevice::with_emulator(
+            device_info("a"),
+            EmulatedKeyboard::new(),
+        ));
+        let manager = manager(&transport);
+        let keyboard = Keyboard {
+            read_back: true,
+            ..keyboard("a")
+        };
+        let options = DeliveryOptions {
+            verify: true,
+            ..Default::default()
+        };
+
+        let report = manager
+            .send_config("a", &keyboard, &lighting(16), &options)
+            .unwrap();
+
+        assert_eq!(report.groups[0].verified, Some(true));
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn unit_id_ignores_the_collection_of_windows_paths() {
+        let col01 = r"\\?\HID#VID_258A&PID_0049&MI_01&Col01#7&2a3f1c&0&0000#{4d1e55b2}";
+        let col02 = r"\\?\HID#VID_258A&PID_0049&MI_01&Col02#7&2a3f1c&0&0001#{4d1e55b2}";
+        let other_unit = r"\\?\HID#VID_258A&PID_0049&MI_01&Col01#7&11aa22&0&0000#{4d1e55b2}";
+
+        assert_eq!(physical_unit_id(col01), physical_unit_id(col02));
+        assert_ne!(physical_unit_id(col01), physical_unit_id(other_unit));
+    }
+
+    #[test]
+    fn unit_id_ignores_the_interface_of_libusb_paths() {
+        assert_eq!(physical_unit_id("1-2.3:1.0"), physical_unit_id("1-2.3:1.1"));
+        assert_ne!(physical_unit_id("1-2.3:1.0"), physical_unit_id("1-2.4:1.0"));
+        assert_eq!(physical_unit_id("/dev/hidraw3"), "/dev/hidraw3");
+    }
+
+    #[test]
+    fn identical_boards_with_the_same_serial_are_listed_separately() {
+        let transport = MockTransport::new();
+        for path in ["/dev/hidraw1", "/dev/hidraw4"] {
+            transport.add_device(MockDevice::new(DeviceInfo {
+                serial_number: Some("0001".to_string()),
+                ..device_info(path)
+            }));
+        }
+
+        let keyboards = manager(&transport).scan_keyboards().unwrap();
+
+        assert_eq!(paths(&keyboards), ["/dev/hidraw1", "/dev/hidraw4"]);
+        assert!(keyboards
+            .iter()
+            .all(|k| k.serial_number.as_deref() == Some("0001")));
+    }
+
+    #[test]
+    fn other_interfaces_of_a_unit_are_listed_once() {
+        let transport = MockTransport::new();
+        for path in ["1-2:1.0", "1-2:1.1"] {
+            transport.add_device(MockDevice::new(device_info(path)));
+        }
+
+        let keyboards = manager(&transport).scan_keyboards().unwrap();
+
+        assert_eq!(paths(&keyboards), ["1-2:1.0"]);
+    }
+
+    const A1: KeyboardUsbId = KeyboardUsbId {
+        vid: SUPPORTED_VID,
+        pid: 0xa1,
+    };
+
+    fn watched(roots: &DefinitionRoots) -> (HidManager, HashMap<PathBuf, SystemTime>) {
+        let manager = HidManager::with_transport(
+            Box::new(MockTransport::new()),
+            DefinitionRegistry::load(roots.clone()),
+        );
+        (manager, definition_stamps(roots))
+    }
+
+    fn bundled_name(id: KeyboardUsbId) -> String {
+        let registry = DefinitionRegistry::load(bundled_roots());
+        registry.keyboard(id, "").unwrap().name
+    }
+
+    #[test]
+    fn override_root_created_while_running_is_picked_up() {
+        let no_overrides = bundled_roots();
+        let (manager, mut known) = watched(&no_overrides);
+        assert!(poll_definitions(&manager, no_overrides, &mut known).is_empty());
+
+        let root = TempRoot::new("watch-created");
+        root.add(0xa1, "Override");
+        let changes = poll_definitions(&manager, root.roots(), &mut known);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].id, A1);
+        assert_eq!(changes[0].keyboard.as_ref().unwrap().name, "Override");
+        assert!(poll_definitions(&manager, root.roots(), &mut known).is_empty());
+    }
+
+    #[test]
+    fn removed_override_falls_back_to_the_bundled_definition() {
+        let root = TempRoot::new("watch-removed");
+        root.add(0xa1, "Override");
+        let (manager, mut known) = watched(&root.roots());
+
+        fs::remove_dir_all(&root.0).unwrap();
+        let changes = poll_definitions(&manager, root.roots(), &mut known);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].keyboard.as_ref().unwrap().name, bundled_name(A1));
+    }
+
+    #[test]
+    fn edited_definition_is_reloaded() {
+        let root = TempRoot::new("watch-edited");
+        root.add(0xa1, "Override");
+        let (manager, mut known) = watched(&root.roots());
+
+        let relative = definition_relative_path(A1.vid, A1.pid);
+        root.write(&relative, "{");
+        // Keep the edit visible even on file systems with a coarse modification time
+        fs::File::options()
+            .write(true)
+            .open(root.0.join(&relative))
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let changes = poll_definitions(&manager, root.roots(), &mut known);
+        assert!(changes[0].keyboard.is_none());
+        assert!(matches!(
+            changes[0].error,
+            Some(AppError::DefinitionInvalid { .. })
+        ));
+
+        root.add(0xa1, "Renamed");
+        let changes = poll_definitions(&manager, root.roots(), &mut known);
+        assert_eq!(changes[0].keyboard.as_ref().unwrap().name, "Renamed");
+        assert!(changes[0].error.is_none());
+    }
+}
@@ -0,0 +1,1143 @@
+use crate::models::{KeyCode, KeyLayer, KeyMapping, KeyMappingConfig, Keyboard};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// HID usage page of regular keyboard keys
+pub const KEYBOARD_USAGE_PAGE: u16 = 0x07;
+/// HID usage page of media and application keys
+pub const CONSUMER_USAGE_PAGE: u16 = 0x0c;
+
+/// Prefix the firmware uses for consumer page codes
+const CONSUMER_PREFIX: u32 = 0x0100_0000;
+/// First keyboard page usage of the modifier keys (Left Control)
+const FIRST_MODIFIER_USAGE: u16 = 0xe0;
+
+/// USB HID usage of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HidUsage {
+    pub page: u16,
+    pub id: u16,
+}
+
+/// Naming scheme of another remapping tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyNameScheme {
+    /// Linux input event codes (`KEY_A`), as used by keyd and evdev remappers
+    Evdev,
+    /// X11 keysym names (`a`, `Control_L`), as used by xmodmap
+    X11,
+    /// Kanata key names (`a`, `lctl`, `bspc`)
+    Kanata,
+}
+
+/// Names of a key code in other tools
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyNames {
+    pub hid_usage: Option<HidUsage>,
+    pub evdev: Option<&'static str>,
+    pub x11: Option<&'static str>,
+    pub kanata: Option<&'static str>,
+}
+
+/// Linux evdev and X11 keysym names of every named key code that has one
+const KEY_NAMES: &[(KeyCode, &str, Option<&str>)] = &[
+    // Numbers
+    (KeyCode::Key1, "KEY_1", Some("1")),
+    (KeyCode::Key2, "KEY_2", Some("2")),
+    (KeyCode::Key3, "KEY_3", Some("3")),
+    (KeyCode::Key4, "KEY_4", Some("4")),
+    (KeyCode::Key5, "KEY_5", Some("5")),
+    (KeyCode::Key6, "KEY_6", Some("6")),
+    (KeyCode::Key7, "KEY_7", Some("7")),
+    (KeyCode::Key8, "KEY_8", Some("8")),
+    (KeyCode::Key9, "KEY_9", Some("9")),
+    (KeyCode::Key0, "KEY_0", Some("0")),
+    // Letters
+    (KeyCode::KeyA, "KEY_A", Some("a")),
+    (KeyCode::KeyB, "KEY_B", Some("b")),
+    (KeyCode::KeyC, "KEY_C", Some("c")),
+    (KeyCode::KeyD, "KEY_D", Some("d")),
+    (KeyCode::KeyE, "KEY_E", Some("e")),
+    (KeyCode::KeyF, "KEY_F", Some("f")),
+    (KeyCode::KeyG, "KEY_G", Some("g")),
+    (KeyCode::KeyH, "KEY_H", Some("h")),
+    (KeyCode::KeyI, "KEY_I", Some("i")),
+    (KeyCode::KeyJ, "KEY_J", Some("j")),
+    (KeyCode::KeyK, "KEY_K", Some("k")),
+    (KeyCode::KeyL, "KEY_L", Some("l")),
+    (KeyCode::KeyM, "KEY_M", Some("m")),
+    (KeyCode::KeyN, "KEY_N", Some("n")),
+    (KeyCode::KeyO, "KEY_O", Some("o")),
+    (KeyCode::KeyP, "KEY_P", Some("p")),
+    (KeyCode::KeyQ, "KEY_Q", Some("q")),
+    (KeyCode::KeyR, "KEY_R", Some("r")),
+    (KeyCode::KeyS, "KEY_S", Some("s")),
+    (KeyCode::KeyT, "KEY_T", Some("t")),
+    (KeyCode::KeyU, "KEY_U", Some("u")),
+    (KeyCode::KeyV, "KEY_V", Some("v")),
+    (KeyCode::KeyW, "KEY_W", Some("w")),
+    (KeyCode::KeyX, "KEY_X", Some("x")),
+    (KeyCode::KeyY, "KEY_Y", Some("y")),
+    (KeyCode::KeyZ, "KEY_Z", Some("z")),
+    // Symbols
+    (KeyCode::KeyHyphen, "KEY_MINUS", Some("minus")),
+    (KeyCode::KeyEquals, "KEY_EQUAL", Some("equal")),
+    (
+        KeyCode::KeyLeftBracket,
+        "KEY_LEFTBRACE",
+        Some("bracketleft"),
+    ),
+    (
+        KeyCode::KeyRightBracket,
+        "KEY_RIGHTBRACE",
+        Some("bracketright"),
+    ),
+    (KeyCode::KeyBackSlash, "KEY_BACKSLASH", Some("backslash")),
+    (KeyCode::KeySemiColon, "KEY_SEMICOLON", Some("semicolon")),
+    (KeyCode::KeyQuote, "KEY_APOSTROPHE", Some("apostrophe")),
+    (KeyCode::KeyBackQuote, "KEY_GRAVE", Some("grave")),
+    (KeyCode::KeyComma, "KEY_COMMA", Some("comma")),
+    (KeyCode::KeyDot, "KEY_DOT", Some("period")),
+    (KeyCode::KeySlash, "KEY_SLASH", Some("slash")),
+    // Modifiers
+    (KeyCode::KeyLeftControl, "KEY_LEFTCTRL", Some("Control_L")),
+    (KeyCode::KeyLeftShift, "KEY_LEFTSHIFT", Some("Shift_L")),
+    (KeyCode::KeyLeftAlt, "KEY_LEFTALT", Some("Alt_L")),
+    (KeyCode::KeyRightControl, "KEY_RIGHTCTRL", Some("Control_R")),
+    (KeyCode::KeyRightShift, "KEY_RIGHTSHIFT", Some("Shift_R")),
+    (KeyCode::KeyRightAlt, "KEY_RIGHTALT", Some("Alt_R")),
+    (KeyCode::KeyLeftSuper, "KEY_LEFTMETA", Some("Super_L")),
+    (KeyCode::KeyRightSuper, "KEY_RIGHTMETA", Some("Super_R")),
+    // Arrow keys
+    (KeyCode::KeyRight, "KEY_RIGHT", Some("Right")),
+    (KeyCode::KeyLeft, "KEY_LEFT", Some("Left")),
+    (KeyCode::KeyDown, "KEY_DOWN", Some("Down")),
+    (KeyCode::KeyUp, "KEY_UP", Some("Up")),
+    // Function keys
+    (KeyCode::KeyF1, "KEY_F1", Some("F1")),
+    (KeyCode::KeyF2, "KEY_F2", Some("F2")),
+    (KeyCode::KeyF3, "KEY_F3", Some("F3")),
+    (KeyCode::KeyF4, "KEY_F4", Some("F4")),
+    (KeyCode::KeyF5, "KEY_F5", Some("F5")),
+    (KeyCode::KeyF6, "KEY_F6", Some("F6")),
+    (KeyCode::KeyF7, "KEY_F7", Some("F7")),
+    (KeyCode::KeyF8, "KEY_F8", Some("F8")),
+    (KeyCode::KeyF9, "KEY_F9", Some("F9")),
+    (KeyCode::KeyF10, "KEY_F10", Some("F10")),
+    (KeyCode::KeyF11, "KEY_F11", Some("F11")),
+    (KeyCode::KeyF12, "KEY_F12", Some("F12")),
+    // Special keys
+    (KeyCode::KeyEscape, "KEY_ESC", Some("Escape")),
+    (KeyCode::KeyTab, "KEY_TAB", Some("Tab")),
+    (KeyCode::KeyEnter, "KEY_ENTER", Some("Return")),
+    (KeyCode::KeyMenu, "KEY_COMPOSE", Some("Menu")),
+    (KeyCode::KeyInsert, "KEY_INSERT", Some("Insert")),
+    (KeyCode::KeyPause, "KEY_PAUSE", Some("Pause")),
+    (KeyCode::KeySpace, "KEY_SPACE", Some("space")),
+    (KeyCode::KeyHome, "KEY_HOME", Some("Home")),
+    (KeyCode::KeyEnd, "KEY_END", Some("End")),
+    (KeyCode::KeyDelete, "KEY_DELETE", Some("Delete")),
+    (KeyCode::KeyPageDown, "KEY_PAGEDOWN", Some("Page_Down")),
+    (KeyCode::KeyPageUp, "KEY_PAGEUP", Some("Page_Up")),
+    (KeyCode::KeyCapsLock, "KEY_CAPSLOCK", Some("Caps_Lock")),
+    (KeyCode::KeyBackspace, "KEY_BACKSPACE", Some("BackSpace")),
+    (KeyCode::KeyPrintScreen, "KEY_SYSRQ", Some("Print")),
+    (
+        KeyCode::KeyScrollLock,
+        "KEY_SCROLLLOCK",
+        Some("Scroll_Lock"),
+    ),
+    (KeyCode::KeyLeftShiftSplit, "KEY_102ND", Some("less")),
+    // Numpad
+    (KeyCode::KeyNum1, "KEY_KP1", Some("KP_1")),
+    (KeyCode::KeyNum2, "KEY_KP2", Some("KP_2")),
+    (KeyCode::KeyNum3, "KEY_KP3", Some("KP_3")),
+    (KeyCode::KeyNum4, "KEY_KP4", Some("KP_4")),
+    (KeyCode::KeyNum5, "KEY_KP5", Some("KP_5")),
+    (KeyCode::KeyNum6, "KEY_KP6", Some("KP_6")),
+    (KeyCode::KeyNum7, "KEY_KP7", Some("KP_7")),
+    (KeyCode::KeyNum8, "KEY_KP8", Some("KP_8")),
+    (KeyCode::KeyNum9, "KEY_KP9", Some("KP_9")),
+    (KeyCode::KeyNum0, "KEY_KP0", Some("KP_0")),
+    (KeyCode::KeyNumAdd, "KEY_KPPLUS", Some("KP_Add")),
+    (KeyCode::KeyNumSubstract, "KEY_KPMINUS", Some("KP_Subtract")),
+    (
+        KeyCode::KeyNumMultiply,
+        "KEY_KPASTERISK",
+        Some("KP_Multiply"),
+    ),
+    (KeyCode::KeyNumDivide, "KEY_KPSLASH", Some("KP_Divide")),
+    (KeyCode::KeyNumDecimalPoint, "KEY_KPDOT", Some("KP_Decimal")),
+    (KeyCode::KeyNumLock, "KEY_NUMLOCK", Some("Num_Lock")),
+    (KeyCode::KeyNumEnter, "KEY_KPENTER", Some("KP_Enter")),
+    // Function key (no X11 keysym)
+    (KeyCode::KeyFn, "KEY_FN", None),
+    // Consumer controls
+    (KeyCode::ShortcutExplorer, "KEY_FILE", Some("XF86Explorer")),
+    (
+        KeyCode::MultimediaPlayPause,
+        "KEY_PLAYPAUSE",
+        Some("XF86AudioPlay"),
+    ),
+    (KeyCode::MultimediaStop, "KEY_STOPCD", Some("XF86AudioStop")),
+    (
+        KeyCode::MultimediaPrevious,
+        "KEY_PREVIOUSSONG",
+        Some("XF86AudioPrev"),
+    ),
+    (
+        KeyCode::MultimediaNext,
+        "KEY_NEXTSONG",
+        Some("XF86AudioNext"),
+    ),
+    (
+        KeyCode::MultimediaVolumeUp,
+        "KEY_VOLUMEUP",
+        Some("XF86AudioRaiseVolume"),
+    ),
+    (
+        KeyCode::MultimediaVolumeDown,
+        "KEY_VOLUMEDOWN",
+        Some("XF86AudioLowerVolume"),
+    ),
+    (KeyCode::MultimediaMute, "KEY_MUTE", Some("XF86AudioMute")),
+    (KeyCode::KeyCalculator, "KEY_CALC", Some("XF86Calculator")),
+    (
+        KeyCode::MultimediaBrightnessUp,
+        "KEY_BRIGHTNESSUP",
+        Some("XF86MonBrightnessUp"),
+    ),
+    (
+        KeyCode::MultimediaBrightnessDown,
+        "KEY_BRIGHTNESSDOWN",
+        Some("XF86MonBrightnessDown"),
+    ),
+    (
+        KeyCode::MultimediaMediaSelect,
+        "KEY_CONFIG",
+        Some("XF86Tools"),
+    ),
+    (KeyCode::ApplicationMail, "KEY_MAIL", Some("XF86Mail")),
+    (KeyCode::BrowserSearch, "KEY_SEARCH", Some("XF86Search")),
+    (KeyCode::BrowserHome, "KEY_HOMEPAGE", Some("XF86HomePage")),
+    (KeyCode::BrowserBack, "KEY_BACK", Some("XF86Back")),
+    (KeyCode::BrowserForward, "KEY_FORWARD", Some("XF86Forward")),
+    (KeyCode::BrowserStop, "KEY_STOP", Some("XF86Stop")),
+    (KeyCode::BrowserRefresh, "KEY_REFRESH", Some("XF86Refresh")),
+    (
+        KeyCode::BrowserFavorites,
+        "KEY_BOOKMARKS",
+        Some("XF86Favorites"),
+    ),
+];
+
+/// Kanata names of every named key code that has one, in kanata's short form
+const KANATA_NAMES: &[(KeyCode, &str)] = &[
+    // Numbers
+    (KeyCode::Key1, "1"),
+    (KeyCode::Key2, "2"),
+    (KeyCode::Key3, "3"),
+    (KeyCode::Key4, "4"),
+    (KeyCode::Key5, "5"),
+    (KeyCode::Key6, "6"),
+    (KeyCode::Key7, "7"),
+    (KeyCode::Key8, "8"),
+    (KeyCode::Key9, "9"),
+    (KeyCode::Key0, "0"),
+    // Letters
+    (KeyCode::KeyA, "a"),
+    (KeyCode::KeyB, "b"),
+    (KeyCode::KeyC, "c"),
+    (KeyCode::KeyD, "d"),
+    (KeyCode::KeyE, "e"),
+    (KeyCode::KeyF, "f"),
+    (KeyCode::KeyG, "g"),
+    (KeyCode::KeyH, "h"),
+    (KeyCode::KeyI, "i"),
+    (KeyCode::KeyJ, "j"),
+    (KeyCode::KeyK, "k"),
+    (KeyCode::KeyL, "l"),
+    (KeyCode::KeyM, "m"),
+    (KeyCode::KeyN, "n"),
+    (KeyCode::KeyO, "o"),
+    (KeyCode::KeyP, "p"),
+    (KeyCode::KeyQ, "q"),
+    (KeyCode::KeyR, "r"),
+    (KeyCode::KeyS, "s"),
+    (KeyCode::KeyT, "t"),
+    (KeyCode::KeyU, "u"),
+    (KeyCode::KeyV, "v"),
+    (KeyCode::KeyW, "w"),
+    (KeyCode::KeyX, "x"),
+    (KeyCode::KeyY, "y"),
+    (KeyCode::KeyZ, "z"),
+    // Symbols
+    (KeyCode::KeyHyphen, "min"),
+    (KeyCode::KeyEquals, "eql"),
+    (KeyCode::KeyLeftBracket, "lbrc"),
+    (KeyCode::KeyRightBracket, "rbrc"),
+    (KeyCode::KeyBackSlash, "bksl"),
+    (KeyCode::KeySemiColon, "scln"),
+    (KeyCode::KeyQuote, "apo"),
+    (KeyCode::KeyBackQuote, "grv"),
+    (KeyCode::KeyComma, "comm"),
+    (KeyCode::KeyDot, "."),
+    (KeyCode::KeySlash, "/"),
+    // Modifiers
+    (KeyCode::KeyLeftControl, "lctl"),
+    (KeyCode::KeyLeftShift, "lsft"),
+    (KeyCode::KeyLeftAlt, "lalt"),
+    (KeyCode::KeyRightControl, "rctl"),
+    (KeyCode::KeyRightShift, "rsft"),
+    (KeyCode::KeyRightAlt, "ralt"),
+    (KeyCode::KeyLeftSuper, "lmet"),
+    (KeyCode::KeyRightSuper, "rmet"),
+    // Arrow keys
+    (KeyCode::KeyRight, "rght"),
+    (KeyCode::KeyLeft, "left"),
+    (KeyCode::KeyDown, "down"),
+    (KeyCode::KeyUp, "up"),
+    // Function keys
+    (KeyCode::KeyF1, "f1"),
+    (KeyCode::KeyF2, "f2"),
+    (KeyCode::KeyF3, "f3"),
+    (KeyCode::KeyF4, "f4"),
+    (KeyCode::KeyF5, "f5"),
+    (KeyCode::KeyF6, "f6"),
+    (KeyCode::KeyF7, "f7"),
+    (KeyCode::KeyF8, "f8"),
+    (KeyCode::KeyF9, "f9"),
+    (KeyCode::KeyF10, "f10"),
+    (KeyCode::KeyF11, "f11"),
+    (KeyCode::KeyF12, "f12"),
+    // Special keys
+    (KeyCode::KeyEscape, "esc"),
+    (KeyCode::KeyTab, "tab"),
+    (KeyCode::KeyEnter, "ret"),
+    (KeyCode::KeyMenu, "menu"),
+    (KeyCode::KeyInsert, "ins"),
+    (KeyCode::KeyPause, "pause"),
+    (KeyCode::KeySpace, "spc"),
+    (KeyCode::KeyHome, "home"),
+    (KeyCode::KeyEnd, "end"),
+    (KeyCode::KeyDelete, "del"),
+    (KeyCode::KeyPageDown, "pgdn"),
+    (KeyCode::KeyPageUp, "pgup"),
+    (KeyCode::KeyCapsLock, "caps"),
+    (KeyCode::KeyBackspace, "bspc"),
+    (KeyCode::KeyPrintScreen, "prtsc"),
+    (KeyCode::KeyScrollLock, "slck"),
+    (KeyCode::KeyLeftShiftSplit, "nubs"),
+    // Numpad
+    (KeyCode::KeyNum1, "kp1"),
+    (KeyCode::KeyNum2, "kp2"),
+    (KeyCode::KeyNum3, "kp3"),
+    (KeyCode::KeyNum4, "kp4"),
+    (KeyCode::KeyNum5, "kp5"),
+    (KeyCode::KeyNum6, "kp6"),
+    (KeyCode::KeyNum7, "kp7"),
+    (KeyCode::KeyNum8, "kp8"),
+    (KeyCode::KeyNum9, "kp9"),
+    (KeyCode::KeyNum0, "kp0"),
+    (KeyCode::KeyNumAdd, "kp+"),
+    (KeyCode::KeyNumSubstract, "kp-"),
+    (KeyCode::KeyNumMultiply, "kp*"),
+    (KeyCode::KeyNumDivide, "kp/"),
+    (KeyCode::KeyNumDecimalPoint, "kp."),
+    (KeyCode::KeyNumLock, "nlck"),
+    (KeyCode::KeyNumEnter, "kprt"),
+    // Consumer controls
+    (KeyCode::MultimediaPlayPause, "pp"),
+    (KeyCode::MultimediaPrevious, "prev"),
+    (KeyCode::MultimediaNext, "next"),
+    (KeyCode::MultimediaVolumeUp, "volu"),
+    (KeyCode::MultimediaVolumeDown, "vold"),
+    (KeyCode::MultimediaMute, "mute"),
+    (KeyCode::MultimediaBrightnessUp, "brup"),
+    (KeyCode::MultimediaBrightnessDown, "brdown"),
+];
+
+/// Other spellings kanata accepts for the names of `KANATA_NAMES`
+const KANATA_ALIASES: &[(&str, &str)] = &[
+    ("-", "min"),
+    ("minus", "min"),
+    ("=", "eql"),
+    ("equal", "eql"),
+    ("[", "lbrc"),
+    ("]", "rbrc"),
+    ("\\", "bksl"),
+    (";", "scln"),
+    ("'", "apo"),
+    ("apos", "apo"),
+    ("grave", "grv"),
+    (",", "comm"),
+    ("dot", "."),
+    ("slash", "/"),
+    ("lctrl", "lctl"),
+    ("rctrl", "rctl"),
+    ("lshift", "lsft"),
+    ("rshift", "rsft"),
+    ("lmeta", "lmet"),
+    ("rmeta", "rmet"),
+    ("right", "rght"),
+    ("return", "ret"),
+    ("ent", "ret"),
+    ("enter", "ret"),
+    ("comp", "menu"),
+    ("space", "spc"),
+    ("bks", "bspc"),
+    ("backspace", "bspc"),
+    ("prnt", "prtsc"),
+    ("scrlck", "slck"),
+    ("102d", "nubs"),
+    ("lsgt", "nubs"),
+    ("nlk", "nlck"),
+    ("voldwn", "vold"),
+    ("brdwn", "brdown"),
+];
+
+impl KeyCode {
+    /// HID usage the firmware sends for this code.
+    ///
+    /// Returns `None` for chords and for codes without a standard usage (e.g. Fn).
+    pub fn to_hid_usage(self) -> Option<HidUsage> {
+        let value = self.to_u32();
+
+        if value & 0xff00_0000 == CONSUMER_PREFIX {
+            return (value & 0x00ff_0000 == 0).then_some(HidUsage {
+                page: CONSUMER_USAGE_PAGE,
+                id: (value & 0xffff) as u16,
+            });
+        }
+
+        let modifiers = (value >> 16) & 0xff;
+        let usage = ((value >> 8) & 0xff) as u16;
+
+        match (modifiers, usage) {
+            // A single modifier key
+            (bits, 0) if bits.count_ones() == 1 => Some(HidUsage {
+                page: KEYBOARD_USAGE_PAGE,
+                id: FIRST_MODIFIER_USAGE + bits.trailing_zeros() as u16,
+            }),
+            (0, usage) if usage != 0 && value & 0xff == 0 && self != KeyCode::KeyFn => {
+                Some(HidUsage {
+                    page: KEYBOARD_USAGE_PAGE,
+                    id: usage,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Key code for a HID usage of the keyboard or consumer page
+    pub fn from_hid_usage(usage: HidUsage) -> Option<KeyCode> {
+        match (usage.page, usage.id) {
+            (KEYBOARD_USAGE_PAGE, id @ 0xe0..=0xe7) => {
+                Some(KeyCode::from_u32(1u32 << (16 + id - FIRST_MODIFIER_USAGE)))
+            }
+            (KEYBOARD_USAGE_PAGE, id @ 0x04..=0xdf) => {
+                // 0xb0 is Keypad 00, which the firmware uses for Fn instead
+                Some(KeyCode::from_u32((id as u32) << 8)).filter(|code| *code != KeyCode::KeyFn)
+            }
+            (CONSUMER_USAGE_PAGE, id) if id != 0 => {
+                Some(KeyCode::from_u32(CONSUMER_PREFIX | id as u32))
+            }
+            _ => None,
+        }
+    }
+
+    /// Linux evdev name of the code (e.g. `KEY_LEFTCTRL`)
+    pub fn to_evdev_name(self) -> Option<&'static str> {
+        KEY_NAMES
+            .iter()
+            .find(|(code, _, _)| *code == self)
+            .map(|(_, evdev, _)| *evdev)
+    }
+
+    /// X11 keysym name of the code (e.g. `Control_L`)
+    pub fn to_x11_keysym(self) -> Option<&'static str> {
+        KEY_NAMES
+            .iter()
+            .find(|(code, _, _)| *code == self)
+            .and_then(|(_, _, x11)| *x11)
+    }
+
+    /// Parse a Linux evdev name; the `KEY_` prefix and case are optional, so keyd
+    /// style names (`leftctrl`, `a`) are accepted too
+    pub fn from_evdev_name(name: &str) -> Option<KeyCode> {
+        let name = name.to_ascii_uppercase();
+        let name = name.strip_prefix("KEY_").unwrap_or(&name);
+
+        KEY_NAMES
+            .iter()
+            .find(|(_, evdev, _)| evdev[4..] == *name)
+            .map(|(code, _, _)| *code)
+    }
+
+    /// Kanata name of the code (e.g. `lctl`)
+    pub fn to_kanata_name(self) -> Option<&'static str> {
+        KANATA_NAMES
+            .iter()
+            .find(|(code, _)| *code == self)
+            .map(|(_, kanata)| *kanata)
+    }
+
+    /// Parse a kanata name, in its short form or one of its long spellings
+    pub fn from_kanata_name(name: &str) -> Option<KeyCode> {
+        let name = KANATA_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, canonical)| *canonical);
+
+        KANATA_NAMES
+            .iter()
+            .find(|(_, kanata)| *kanata == name)
+            .map(|(code, _)| *code)
+    }
+
+    /// Parse an X11 keysym name (case-sensitive, as keysyms are)
+    pub fn from_x11_keysym(name: &str) -> Option<KeyCode> {
+        let name = match name {
+            "Next" => "Page_Down",
+            "Prior" => "Page_Up",
+            "ISO_Level3_Shift" => "Alt_R",
+            "Meta_L" => "Super_L",
+            "Meta_R" => "Super_R",
+            name => name,
+        };
+
+        KEY_NAMES
+            .iter()
+            .find(|(_, _, x11)| *x11 == Some(name))
+            .map(|(code, _, _)| *code)
+    }
+
+    /// Name of the code in another tool
+    pub fn to_name(self, scheme: KeyNameScheme) -> Option<&'static str> {
+        match scheme {
+            KeyNameScheme::Evdev => self.to_evdev_name(),
+            KeyNameScheme::X11 => self.to_x11_keysym(),
+            KeyNameScheme::Kanata => self.to_kanata_name(),
+        }
+    }
+
+    /// Parse a key name of another tool
+    pub fn from_name(scheme: KeyNameScheme, name: &str) -> Option<KeyCode> {
+        match scheme {
+            KeyNameScheme::Evdev => KeyCode::from_evdev_name(name),
+            KeyNameScheme::X11 => KeyCode::from_x11_keysym(name),
+            KeyNameScheme::Kanata => KeyCode::from_kanata_name(name),
+        }
+    }
+
+    /// Every known name of the code
+    pub fn names(self) -> KeyNames {
+        KeyNames {
+            hid_usage: self.to_hid_usage(),
+            evdev: self.to_evdev_name(),
+            x11: self.to_x11_keysym(),
+            kanata: self.to_kanata_name(),
+        }
+    }
+}
+
+/// Config file format of another remapping tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingFileFormat {
+    /// keyd `[main]` section (`capslock = esc`)
+    Keyd,
+    /// xmodmap `keysym` lines (`keysym Caps_Lock = Escape`)
+    Xmodmap,
+    /// kanata `defsrc` block and the `deflayer` block that follows it
+    Kanata,
+}
+
+impl MappingFileFormat {
+    /// Key names the format uses
+    pub fn scheme(self) -> KeyNameScheme {
+        match self {
+            MappingFileFormat::Keyd => KeyNameScheme::Evdev,
+            MappingFileFormat::Xmodmap => KeyNameScheme::X11,
+            MappingFileFormat::Kanata => KeyNameScheme::Kanata,
+        }
+    }
+
+    /// Name of `code` as written in the file
+    fn key_name(self, code: KeyCode) -> Option<String> {
+        let name = code.to_name(self.scheme())?;
+        Some(match self {
+            // keyd takes the evdev names without prefix, in lowercase
+            MappingFileFormat::Keyd => name[4..].to_ascii_lowercase(),
+            _ => name.to_string(),
+        })
+    }
+}
+
+/// Base layer remaps written in another tool's format
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingExport {
+    pub text: String,
+    /// Remaps left out because the format has no name for one of their keys
+    pub warnings: Vec<String>,
+}
+
+/// Base layer remaps read from another tool's config file
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingImport {
+    pub mappings: Vec<KeyMapping>,
+    /// Entries of the file that could not be applied to the keyboard
+    pub warnings: Vec<String>,
+}
+
+/// Name the kanata layer is exported under
+const KANATA_LAYER_NAME: &str = "rk-configurator";
+
+/// Write the base layer remaps of `config` as a `format` config file.
+///
+/// A remap is written with the default code of the key as source, so the file
+/// remaps the same physical keys when used with the other tool.
+pub fn export_mappings(
+    keyboard: &Keyboard,
+    config: &KeyMappingConfig,
+    format: MappingFileFormat,
+) -> MappingExport {
+    let mut remaps = Vec::new();
+    let mut warnings = Vec::new();
+
+    for mapping in config.layer(KeyLayer::Base) {
+        let Some(key) = keyboard
+            .keys
+            .iter()
+            .find(|key| key.buffer_index == mapping.buffer_index)
+        else {
+            warnings.push(format!("No key at index {}", mapping.buffer_index));
+            continue;
+        };
+        if mapping.key_code == key.key_code {
+            continue;
+        }
+
+        match (
+            format.key_name(key.key_code),
+            format.key_name(mapping.key_code),
+        ) {
+            (Some(from), Some(to)) => remaps.push((from, to)),
+            _ => warnings.push(format!(
+                "{:?} -> {:?} cannot be written for {:?}",
+                key.key_code, mapping.key_code, format
+            )),
+        }
+    }
+
+    let mut text = String::new();
+    match format {
+        MappingFileFormat::Keyd => {
+            text.push_str("[ids]\n*\n\n[main]\n");
+            for (from, to) in &remaps {
+                let _ = writeln!(text, "{} = {}", from, to);
+            }
+        }
+        MappingFileFormat::Xmodmap => {
+            for (from, to) in &remaps {
+                let _ = writeln!(text, "keysym {} = {}", from, to);
+            }
+        }
+        MappingFileFormat::Kanata => {
+            text.push_str("(defsrc\n");
+            for (from, _) in &remaps {
+                let _ = writeln!(text, "  {}", from);
+            }
+            let _ = writeln!(text, ")\n\n(deflayer {}", KANATA_LAYER_NAME);
+            for (_, to) in &remaps {
+                let _ = writeln!(text, "  {}", to);
+            }
+            text.push_str(")\n");
+        }
+    }
+
+    MappingExport { text, warnings }
+}
+
+/// Read the remaps of a `format` config file as base layer mappings of `keyboard`.
+///
+/// Entries naming keys the keyboard does not have, or actions other than a plain key,
+/// are skipped with a warning; an error is only returned when the file cannot be parsed.
+pub fn import_mappings(
+    keyboard: &Keyboard,
+    format: MappingFileFormat,
+    text: &str,
+) -> Result<MappingImport, String> {
+    let mut warnings = Vec::new();
+    let entries = match format {
+        MappingFileFormat::Keyd => parse_keyd(text)?,
+        MappingFileFormat::Xmodmap => parse_xmodmap(text, &mut warnings)?,
+        MappingFileFormat::Kanata => parse_kanata(text, &mut warnings)?,
+    };
+
+    let scheme = format.scheme();
+    let mut mappings: Vec<KeyMapping> = Vec::new();
+    for (from, to) in entries {
+        let (Some(from_code), Some(to_code)) = (
+            KeyCode::from_name(scheme, &from),
+            KeyCode::from_name(scheme, &to),
+        ) else {
+            warnings.push(format!("{} -> {}: unknown key name", from, to));
+            continue;
+        };
+        let Some(key) = keyboard
+            .keys
+            .iter()
+            .find(|key| key.remappable && key.key_code == from_code)
+        else {
+            warnings.push(format!("{}: no remappable key on {}", from, keyboard.name));
+            continue;
+        };
+
+        // A later entry for the same key wins, as in the tools themselves
+        mappings.retain(|mapping| mapping.buffer_index != key.buffer_index);
+        mappings.push(KeyMapping {
+            buffer_index: key.buffer_index,
+            key_code: to_code,
+            layer: KeyLayer::Base,
+        });
+    }
+
+    Ok(MappingImport { mappings, warnings })
+}
+
+/// `key = key` lines of the keyd `[main]` section
+fn parse_keyd(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            section = name.trim().to_string();
+            continue;
+        }
+        if section != "main" {
+            continue;
+        }
+
+        let (from, to) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected \"key = key\"", number + 1))?;
+        entries.push((from.trim().to_string(), to.trim().to_string()));
+    }
+
+    Ok(entries)
+}
+
+/// `keysym name = name` lines of an xmodmap file
+fn parse_xmodmap(text: &str, warnings: &mut Vec<String>) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') {
+            continue;
+        }
+        let Some(rest) = line.strip_prefix("keysym ") else {
+            warnings.push(format!(
+                "line {}: only keysym lines are imported",
+                number + 1
+            ));
+            continue;
+        };
+
+        let (from, to) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected \"keysym name = name\"", number + 1))?;
+        // Only the unshifted keysym can be stored on the keyboard
+        match to.split_whitespace().next() {
+            Some(to) => entries.push((from.trim().to_string(), to.to_string())),
+            None => warnings.push(format!(
+                "line {}: clearing a key is not supported",
+                number + 1
+            )),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Kanata S-expression
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+/// Key pairs of the `defsrc` block and the first `deflayer` block of a kanata file
+fn parse_kanata(text: &str, warnings: &mut Vec<String>) -> Result<Vec<(String, String)>, String> {
+    let blocks = parse_sexps(text)?;
+    let block = |name: &str| {
+        blocks.iter().find_map(|block| match block {
+            Sexp::List(items) => match items.first() {
+                Some(Sexp::Atom(head)) if head == name => Some(&items[1..]),
+                _ => None,
+            },
+            Sexp::Atom(_) => None,
+        })
+    };
+
+    let source = block("defsrc").ok_or("no defsrc block")?;
+    // The layer name comes first
+    let layer = block("deflayer")
+        .and_then(|items| items.get(1..))
+        .ok_or("no deflayer block")?;
+    if source.len() != layer.len() {
+        return Err(format!(
+            "deflayer has {} keys but defsrc has {}",
+            layer.len(),
+            source.len()
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for (from, to) in source.iter().zip(layer) {
+        let Sexp::Atom(from) = from else {
+            return Err("defsrc may only list keys".to_string());
+        };
+        match to {
+            // Transparent: the key keeps its default
+            Sexp::Atom(to) if to == "_" => {}
+            Sexp::Atom(to) => entries.push((from.clone(), to.clone())),
+            Sexp::List(_) => warnings.push(format!("{}: only plain keys are imported", from)),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Parse the top-level S-expressions of a kanata file, skipping `;;` and `#| |#` comments
+fn parse_sexps(text: &str) -> Result<Vec<Sexp>, String> {
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut atom = String::new();
+    let mut chars = text.chars().peekable();
+
+    let flush = |atom: &mut String, stack: &mut Vec<Vec<Sexp>>| {
+        if !atom.is_empty() {
+            if let Some(list) = stack.last_mut() {
+                list.push(Sexp::Atom(std::mem::take(atom)));
+            }
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' if chars.peek() == Some(&';') => {
+                flush(&mut atom, &mut stack);
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '#' if chars.peek() == Some(&'|') => {
+                flush(&mut atom, &mut stack);
+                chars.next();
+                let mut previous = None;
+                loop {
+                    match chars.next() {
+                        Some('#') if previous == Some('|') => break,
+                        Some(c) => previous = Some(c),
+                        None => return Err("unterminated #| comment".to_string()),
+                    }
+                }
+            }
+            '(' => {
+                flush(&mut atom, &mut stack);
+                stack.push(Vec::new());
+            }
+            ')' => {
+                flush(&mut atom, &mut stack);
+                if stack.len() < 2 {
+                    return Err("unbalanced ')'".to_string());
+                }
+                let list = stack.pop().unwrap_or_default();
+                if let Some(parent) = stack.last_mut() {
+                    parent.push(Sexp::List(list));
+                }
+            }
+            c if c.is_whitespace() => flush(&mut atom, &mut stack),
+            c => atom.push(c),
+        }
+    }
+    flush(&mut atom, &mut stack);
+
+    if stack.len() != 1 {
+        return Err("unbalanced '('".to_string());
+    }
+    Ok(stack.pop().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{list_key_codes, Key};
+    use crate::test_support::{key, keyboard};
+
+    const SCHEMES: [KeyNameScheme; 3] = [
+        KeyNameScheme::Evdev,
+        KeyNameScheme::X11,
+        KeyNameScheme::Kanata,
+    ];
+    const FORMATS: [MappingFileFormat; 3] = [
+        MappingFileFormat::Keyd,
+        MappingFileFormat::Xmodmap,
+        MappingFileFormat::Kanata,
+    ];
+
+    fn keys() -> Vec<Key> {
+        vec![
+            key(0, KeyCode::KeyEscape),
+            key(3, KeyCode::KeyCapsLock),
+            key(5, KeyCode::KeyLeftControl),
+            key(12, KeyCode::KeyF1),
+        ]
+    }
+
+    fn mapping(buffer_index: u8, key_code: KeyCode) -> KeyMapping {
+        KeyMapping {
+            buffer_index,
+            key_code,
+            layer: KeyLayer::Base,
+        }
+    }
+
+    fn remaps(import: &MappingImport) -> Vec<(u8, KeyCode)> {
+        import
+            .mappings
+            .iter()
+            .map(|mapping| (mapping.buffer_index, mapping.key_code))
+            .collect()
+    }
+
+    #[test]
+    fn names_round_trip_in_every_scheme() {
+        for scheme in SCHEMES {
+            for info in list_key_codes() {
+                let code = KeyCode::from_u32(info.value);
+                if let Some(name) = code.to_name(scheme) {
+                    assert_eq!(KeyCode::from_name(scheme, name), Some(code), "{}", name);
+                }
+            }
+        }
+        assert_eq!(KeyCode::KeyBackspace.to_kanata_name(), Some("bspc"));
+        assert_eq!(
+            KeyCode::KeyLeftControl.to_evdev_name(),
+            Some("KEY_LEFTCTRL")
+        );
+        assert_eq!(KeyCode::KeyLeftControl.to_x11_keysym(), Some("Control_L"));
+    }
+
+    #[test]
+    fn hid_usages_round_trip() {
+        for info in list_key_codes() {
+            let code = KeyCode::from_u32(info.value);
+            if let Some(usage) = code.to_hid_usage() {
+                assert_eq!(KeyCode::from_hid_usage(usage), Some(code), "{}", info.name);
+            }
+        }
+        assert_eq!(
+            KeyCode::KeyLeftShift.to_hid_usage(),
+            Some(HidUsage {
+                page: KEYBOARD_USAGE_PAGE,
+                id: 0xe1
+            })
+        );
+        assert_eq!(KeyCode::ShortcutCopy.to_hid_usage(), None);
+        assert_eq!(KeyCode::KeyFn.to_hid_usage(), None);
+    }
+
+    #[test]
+    fn only_keyboard_and_consumer_usages_are_translated() {
+        let power = HidUsage {
+            page: 0x01,
+            id: 0x81,
+        };
+
+        assert_eq!(KeyCode::from_hid_usage(power), None);
+        assert_eq!(KeyCode::from_u32(0x0200_0081).to_hid_usage(), None);
+        assert_eq!(KeyCode::macro_key(1).to_hid_usage(), None);
+        assert_eq!(
+            KeyCode::MultimediaMute.to_hid_usage(),
+            Some(HidUsage {
+                page: CONSUMER_USAGE_PAGE,
+                id: 0xe2
+            })
+        );
+    }
+
+    #[test]
+    fn alternative_spellings_are_accepted() {
+        assert_eq!(
+            KeyCode::from_kanata_name("lctrl"),
+            Some(KeyCode::KeyLeftControl)
+        );
+        assert_eq!(KeyCode::from_kanata_name("'"), Some(KeyCode::KeyQuote));
+        assert_eq!(
+            KeyCode::from_kanata_name("grave"),
+            Some(KeyCode::KeyBackQuote)
+        );
+        assert_eq!(
+            KeyCode::from_evdev_name("leftctrl"),
+            Some(KeyCode::KeyLeftControl)
+        );
+        assert_eq!(KeyCode::from_x11_keysym("Prior"), Some(KeyCode::KeyPageUp));
+        assert_eq!(KeyCode::from_kanata_name("LCTL"), None);
+    }
+
+    #[test]
+    fn mapping_files_round_trip_in_every_format() {
+        let config = KeyMappingConfig {
+            mappings: vec![
+                mapping(0, KeyCode::KeyEscape),
+                mapping(3, KeyCode::KeyEscape),
+                mapping(5, KeyCode::KeyCapsLock),
+                mapping(12, KeyCode::MultimediaVolumeUp),
+            ],
+        };
+
+        for format in FORMATS {
+            let export = export_mappings(&keyboard(keys()), &config, format);
+            assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+
+            let import = import_mappings(&keyboard(keys()), format, &export.text).unwrap();
+            assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+            assert_eq!(
+                remaps(&import),
+                [
+                    (3, KeyCode::KeyEscape),
+                    (5, KeyCode::KeyCapsLock),
+                    (12, KeyCode::MultimediaVolumeUp),
+                ],
+                "{}",
+                export.text
+            );
+        }
+    }
+
+    #[test]
+    fn exported_files_use_each_tools_syntax() {
+        let config = KeyMappingConfig {
+            mappings: vec![mapping(3, KeyCode::KeyEscape)],
+        };
+        let text = |format| export_mappings(&keyboard(keys()), &config, format).text;
+
+        assert_eq!(
+            text(MappingFileFormat::Keyd),
+            "[ids]\n*\n\n[main]\ncapslock = esc\n"
+        );
+        assert_eq!(
+            text(MappingFileFormat::Xmodmap),
+            "keysym Caps_Lock = Escape\n"
+        );
+        assert_eq!(
+            text(MappingFileFormat::Kanata),
+            "(defsrc\n  caps\n)\n\n(deflayer rk-configurator\n  esc\n)\n"
+        );
+    }
+
+    #[test]
+    fn remaps_without_a_name_are_left_out_of_exports() {
+        let config = KeyMappingConfig {
+            mappings: vec![
+                mapping(3, KeyCode::ShortcutCopy),
+                mapping(5, KeyCode::KeyEscape),
+            ],
+        };
+
+        let export = export_mappings(&keyboard(keys()), &config, MappingFileFormat::Keyd);
+
+        assert_eq!(export.text, "[ids]\n*\n\n[main]\nleftctrl = esc\n");
+        assert_eq!(export.warnings.len(), 1);
+    }
+
+    #[test]
+    fn keyd_import_reads_the_main_section_only() {
+        let text = "[ids]\n*\n\n# Swap escape and caps lock\n[main]\ncapslock = esc\nesc = capslock\n\n[control]\nf1 = volumeup\n";
+
+        let import = import_mappings(&keyboard(keys()), MappingFileFormat::Keyd, text).unwrap();
+
+        assert_eq!(
+            remaps(&import),
+            [(3, KeyCode::KeyEscape), (0, KeyCode::KeyCapsLock)]
+        );
+        assert!(import_mappings(
+            &keyboard(keys()),
+            MappingFileFormat::Keyd,
+            "[main]\ncapslock\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn xmodmap_import_keeps_the_unshifted_keysym() {
+        let text = "! Caps lock as escape\nclear lock\nkeysym Caps_Lock = Escape Escape\nkeysym F1 = XF86AudioRaiseVolume\n";
+
+        let import = import_mappings(&keyboard(keys()), MappingFileFormat::Xmodmap, text).unwrap();
+
+        assert_eq!(
+            remaps(&import),
+            [(3, KeyCode::KeyEscape), (12, KeyCode::MultimediaVolumeUp)]
+        );
+        assert_eq!(import.warnings, ["line 2: only keysym lines are imported"]);
+    }
+
+    #[test]
+    fn kanata_import_pairs_defsrc_with_the_first_layer() {
+        let text = "
+            ;; Home row experiments
+            (defcfg process-unmapped-keys yes)
+            (defsrc caps lctl esc f1)
+            #| transparent keys
+               keep their default |#
+            (deflayer base esc (tap-hold 200 200 caps lctl) _ volu)
+            (deflayer other _ _ _ _)
+        ";
+
+        let import = import_mappings(&keyboard(keys()), MappingFileFormat::Kanata, text).unwrap();
+
+        assert_eq!(
+            remaps(&import),
+            [(3, KeyCode::KeyEscape), (12, KeyCode::MultimediaVolumeUp)]
+        );
+        assert_eq!(import.warnings, ["lctl: only plain keys are imported"]);
+    }
+
+    #[test]
+    fn malformed_kanata_files_are_rejected() {
+        for text in [
+            "(defsrc caps esc)\n(deflayer base esc)",
+            "(deflayer base esc)",
+            "(defsrc caps)\n(deflayer base esc",
+            "(defsrc caps))",
+        ] {
+            assert!(
+                import_mappings(&keyboard(keys()), MappingFileFormat::Kanata, text).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn entries_the_keyboard_cannot_take_are_skipped() {
+        let text = "[main]\nrightctrl = esc\ncapslock = frobnicate\nf1 = a\nf1 = b\n";
+
+        let import = import_mappings(&keyboard(keys()), MappingFileFormat::Keyd, text).unwrap();
+
+        assert_eq!(remaps(&import), [(12, KeyCode::KeyB)]);
+        assert_eq!(import.warnings.len(), 2);
+    }
+}
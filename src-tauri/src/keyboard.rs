@@ -1,130 +1,311 @@
-use crate::models::{Key, KeyCode, Keyboard, KeyboardUsbId};
-use serde_json::Value;
+use crate::definition::{parse_definition_json, KeyboardDefinition};
+use crate::error::AppError;
+use crate::models::{Key, KeyCode, Keyboard, KeyboardUsbId, MacroLimits};
 use std::fs;
 use std::path::{Path, PathBuf};
 
-/// Load keyboard configuration from JSON file
+/// How a definition file naming a key code the app does not know is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownKeyPolicy {
+    /// Reject the whole file
+    Reject,
+    /// Keep the key with `remappable: false`, so it can still be colored
+    Unmappable,
+}
+
+/// How strictly definition files are checked when loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefinitionOptions {
+    pub unknown_keys: UnknownKeyPolicy,
+    /// Reject fields the definition format does not define instead of logging them
+    pub deny_unknown_fields: bool,
+}
+
+impl DefinitionOptions {
+    /// Load whatever can be used, as the app does for connected keyboards
+    pub const LENIENT: DefinitionOptions = DefinitionOptions {
+        unknown_keys: UnknownKeyPolicy::Unmappable,
+        deny_unknown_fields: false,
+    };
+    /// Reject anything unexpected, for checking definition files
+    pub const STRICT: DefinitionOptions = DefinitionOptions {
+        unknown_keys: UnknownKeyPolicy::Reject,
+        deny_unknown_fields: true,
+    };
+}
+
+/// Environment variable naming an alternate definitions root
+pub const KEYBOARDS_DIR_ENV: &str = "RK_KEYBOARDS_DIR";
+
+/// Command line flag naming an alternate definitions root, takes precedence over the environment
+pub const KEYBOARDS_DIR_FLAG: &str = "--keyboards-dir";
+
+/// Directories keyboard definitions are loaded from, each laid out as
+/// `<vid>/configs/<pid>.json` and `<vid>/images/<pid>.png`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionRoots {
+    /// Searched before the bundled definitions, highest priority first
+    pub overrides: Vec<PathBuf>,
+    /// Definitions shipped with the app
+    pub bundled: PathBuf,
+}
+
+impl DefinitionRoots {
+    /// The alternate root from the command line or environment, the user definitions
+    /// directory and the bundled definitions
+    pub fn discover() -> Self {
+        if let Some(dir) = alternate_definitions_dir().filter(|dir| !dir.is_dir()) {
+            eprintln!(
+                "[Resource] Alternate keyboards dir {} does not exist, ignoring it",
+                dir.display()
+            );
+        }
+
+        Self {
+            overrides: override_dirs(),
+            bundled: get_resource_dir(),
+        }
+    }
+
+    /// The same roots with the override directories looked up again, e.g. after the
+    /// user definitions directory was created while the app runs
+    pub fn rediscover(&self) -> Self {
+        Self {
+            overrides: override_dirs(),
+            bundled: self.bundled.clone(),
+        }
+    }
+
+    /// Every root with whether it is the bundled one, highest priority first
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, bool)> {
+        self.overrides
+            .iter()
+            .map(|root| (root.as_path(), false))
+            .chain(std::iter::once((self.bundled.as_path(), true)))
+    }
+
+    /// First root holding `relative`, and whether it is the bundled one
+    fn find(&self, relative: &Path) -> Option<(&Path, bool)> {
+        self.iter().find(|(root, _)| root.join(relative).is_file())
+    }
+}
+
+/// Existing override roots: the alternate root, then the user definitions directory
+fn override_dirs() -> Vec<PathBuf> {
+    [alternate_definitions_dir(), user_definitions_dir()]
+        .into_iter()
+        .flatten()
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// Per-user definitions directory, e.g. `~/.config/com.ripwords.rk-configurator/keyboards`
+/// on Linux (honouring `XDG_CONFIG_HOME`)
+pub fn user_definitions_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("com.ripwords.rk-configurator").join("keyboards"))
+}
+
+/// Alternate root given as `--keyboards-dir <path>`, `--keyboards-dir=<path>` or in
+/// `RK_KEYBOARDS_DIR`
+fn alternate_definitions_dir() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == KEYBOARDS_DIR_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(value) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(KEYBOARDS_DIR_FLAG))
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(PathBuf::from(value));
+        }
+    }
+
+    std::env::var_os(KEYBOARDS_DIR_ENV)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Load keyboard configuration from JSON file, taking the first root that defines it
 pub fn load_keyboard_config(
     vid: u16,
     pid: u16,
     device_path: &str,
-    resource_dir: &Path,
-) -> Result<Keyboard, String> {
-    let config_path = resource_dir
-        .join(format!("{:x}", vid))
-        .join("configs")
-        .join(format!("{:x}.json", pid));
-    
+    roots: &DefinitionRoots,
+    options: DefinitionOptions,
+) -> Result<Keyboard, AppError> {
+    let relative_config = definition_relative_path(vid, pid);
+    let Some((root, bundled)) = roots.find(&relative_config) else {
+        return Err(AppError::DefinitionMissing {
+            path: roots.bundled.join(&relative_config).display().to_string(),
+        });
+    };
+
+    load_definition(vid, pid, device_path, root, bundled, options)
+}
+
+/// Load the definition of `vid`/`pid` from one root (`bundled` when it is the app's own)
+pub fn load_definition(
+    vid: u16,
+    pid: u16,
+    device_path: &str,
+    root: &Path,
+    bundled: bool,
+    options: DefinitionOptions,
+) -> Result<Keyboard, AppError> {
+    let config_path = root.join(definition_relative_path(vid, pid));
+    let invalid = |message: String| AppError::DefinitionInvalid {
+        path: config_path.display().to_string(),
+        message,
+    };
+
     if !config_path.exists() {
-        return Err(format!("Config file not found: {}", config_path.display()));
+        return Err(AppError::DefinitionMissing {
+            path: config_path.display().to_string(),
+        });
     }
 
     let config_content = fs::read_to_string(&config_path)
-        .map_err(|e| format!("Failed to read config file: {}", e))?;
+        .map_err(|e| invalid(format!("Failed to read config file: {}", e)))?;
+
+    let (definition, unknown_fields) = parse_definition_json(&config_content).map_err(invalid)?;
 
-    let config: Value = serde_json::from_str(&config_content)
-        .map_err(|e| format!("Failed to parse config JSON: {}", e))?;
+    if !unknown_fields.is_empty() {
+        let message = format!("Unknown fields: {}", unknown_fields.join(", "));
+        if options.deny_unknown_fields {
+            return Err(invalid(message));
+        }
+        eprintln!("[RK-Configurator] {}: {}", config_path.display(), message);
+    }
 
-    if !config["enabled"].as_bool().unwrap_or(false) {
-        return Err("Keyboard is disabled in config".to_string());
+    if !definition.enabled {
+        return Err(AppError::KeyboardDisabled { vid, pid });
     }
 
-    let name = config["name"]
-        .as_str()
-        .ok_or("Missing 'name' field")?
-        .to_string();
+    // The image sits next to the definition, in the same root
+    let image_full_path = root
+        .join(format!("{:x}", vid))
+        .join("images")
+        .join(format!("{:x}.png", pid));
 
-    let top = config["top"].as_array().ok_or("Missing 'top' array")?;
-    let bottom = config["bottom"]
-        .as_array()
-        .ok_or("Missing 'bottom' array")?;
+    if !image_full_path.exists() {
+        return Err(AppError::DefinitionMissing {
+            path: image_full_path.display().to_string(),
+        });
+    }
 
-    let top_left_x = top[0].as_i64().ok_or("Invalid top[0]")? as i32;
-    let top_left_y = top[1].as_i64().ok_or("Invalid top[1]")? as i32;
-    let bottom_right_x = bottom[0].as_i64().ok_or("Invalid bottom[0]")? as i32;
-    let bottom_right_y = bottom[1].as_i64().ok_or("Invalid bottom[1]")? as i32;
+    // Bundled images are resolved as app resources, others by their absolute path
+    let image_path = if bundled {
+        format!("keyboards/{:x}/images/{:x}.png", vid, pid)
+    } else {
+        std::path::absolute(&image_full_path)
+            .unwrap_or(image_full_path)
+            .display()
+            .to_string()
+    };
 
-    let key_map_enabled = config["keyMapEnabled"].as_bool().unwrap_or(false);
-    let light_enabled = config["lightEnabled"].as_bool().unwrap_or(false);
-    let rgb = config["rgb"].as_bool().unwrap_or(false);
+    build_keyboard(
+        definition,
+        KeyboardUsbId { vid, pid },
+        device_path,
+        image_path,
+        options.unknown_keys,
+    )
+    .map_err(invalid)
+}
 
+/// `<vid>/configs/<pid>.json`, relative to a definitions root
+pub fn definition_relative_path(vid: u16, pid: u16) -> PathBuf {
+    PathBuf::from(format!("{:x}", vid))
+        .join("configs")
+        .join(format!("{:x}.json", pid))
+}
+
+/// Turn a parsed definition file into the keyboard layout used by the app
+fn build_keyboard(
+    definition: KeyboardDefinition,
+    id: KeyboardUsbId,
+    device_path: &str,
+    image_path: String,
+    unknown_keys: UnknownKeyPolicy,
+) -> Result<Keyboard, String> {
     // Debug log for RGB status
     eprintln!(
         "[RK-Configurator] Loaded keyboard config: name={}, rgb={}",
-        name, rgb
+        definition.name, definition.rgb
     );
 
     let mut keys = Vec::new();
 
-    if key_map_enabled {
-        let keys_array = config["keys"].as_array().ok_or("Missing 'keys' array")?;
-
-        for key_obj in keys_array {
-            let buffer_index = key_obj["bIndex"]
-                .as_u64()
-                .ok_or("Missing or invalid 'bIndex'")? as u8;
-
-            let key_code_str = key_obj["keyCode"].as_str().ok_or("Missing 'keyCode'")?;
-
-            let key_code = KeyCode::from_str(key_code_str)
-                .ok_or_else(|| format!("Unknown key code: {}", key_code_str))?;
-
-            if key_code == KeyCode::KeyInvalid {
-                continue;
-            }
+    if definition.key_map_enabled {
+        // Without keys the zeroed key map block would unmap every key
+        if definition.keys.is_empty() {
+            return Err("Missing 'keys' array".to_string());
+        }
 
-            let key_top = key_obj["top"].as_array().ok_or("Missing key 'top' array")?;
-            let key_bottom = key_obj["bottom"]
-                .as_array()
-                .ok_or("Missing key 'bottom' array")?;
+        for (i, key) in definition.keys.into_iter().enumerate() {
+            let in_key = |message: String| format!("keys[{}]: {}", i, message);
 
-            let key_top_x = key_top[0].as_i64().ok_or("Invalid key top[0]")? as i32;
-            let key_top_y = key_top[1].as_i64().ok_or("Invalid key top[1]")? as i32;
-            let key_bottom_x = key_bottom[0].as_i64().ok_or("Invalid key bottom[0]")? as i32;
-            let key_bottom_y = key_bottom[1].as_i64().ok_or("Invalid key bottom[1]")? as i32;
+            let (key_code, remappable) = match KeyCode::from_str(&key.key_code) {
+                Some(key_code) => (key_code, true),
+                None => (
+                    KeyCode::KeyInvalid,
+                    unknown_key(&key.key_code, key.b_index, unknown_keys).map_err(in_key)?,
+                ),
+            };
 
             keys.push(Key {
-                buffer_index,
+                buffer_index: key.b_index,
                 key_code,
-                top_x: key_top_x,
-                top_y: key_top_y,
-                bottom_x: key_bottom_x,
-                bottom_y: key_bottom_y,
+                remappable,
+                top_x: key.top[0],
+                top_y: key.top[1],
+                bottom_x: key.bottom[0],
+                bottom_y: key.bottom[1],
             });
         }
     }
 
-    let image_path = format!("keyboards/{:x}/images/{:x}.png", vid, pid);
-
-    // Verify image exists
-    let image_full_path = resource_dir
-        .join(format!("{:x}", vid))
-        .join("images")
-        .join(format!("{:x}.png", pid));
-
-    if !image_full_path.exists() {
-        return Err(format!(
-            "Keyboard image not found: {}",
-            image_full_path.display()
-        ));
-    }
-
     Ok(Keyboard {
-        id: KeyboardUsbId { vid, pid },
+        id,
         path: device_path.to_string(),
-        name,
+        serial_number: None,
+        interface_number: -1,
+        name: definition.name,
         image_path,
         keys,
-        key_map_enabled,
-        light_enabled,
-        rgb,
-        top_left_x,
-        top_left_y,
-        bottom_right_x,
-        bottom_right_y,
+        key_map_enabled: definition.key_map_enabled,
+        macro_limits: definition.macros.map(|macros| MacroLimits {
+            slots: macros.slots,
+            max_steps: macros.max_steps,
+        }),
+        light_enabled: definition.light_enabled,
+        rgb: definition.rgb,
+        read_back: definition.read_back,
+        top_left_x: definition.top[0],
+        top_left_y: definition.top[1],
+        bottom_right_x: definition.bottom[0],
+        bottom_right_y: definition.bottom[1],
     })
 }
 
+/// Apply `policy` to a key code name the app does not know.
+///
+/// Returns `false` (the key is kept but cannot be remapped) or the rejection error.
+fn unknown_key(name: &str, buffer_index: u8, policy: UnknownKeyPolicy) -> Result<bool, String> {
+    match policy {
+        UnknownKeyPolicy::Unmappable => {
+            eprintln!(
+                "[RK-Configurator] Unknown key code {} at index {}, key will not be remappable",
+                name, buffer_index
+            );
+            Ok(false)
+        }
+        UnknownKeyPolicy::Reject => Err(format!("Unknown key code: {}", name)),
+    }
+}
+
 /// Check if a device path matches Windows HID collection pattern
 #[cfg(windows)]
 pub fn matches_windows_path(_path: &str, _vid: u16, _pid: u16, col_number: Option<&str>) -> bool {
@@ -211,3 +392,151 @@ pub fn get_resource_dir() -> PathBuf {
     eprintln!("[Resource] WARNING: Could not find keyboards directory, using fallback");
     PathBuf::from("keyboards")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bundled_root, TempRoot};
+
+    fn definition(key_code: &str) -> KeyboardDefinition {
+        let json = format!(
+            r#"{{
+                "pid": "4f", "name": "Test", "enabled": true,
+                "keyMapEnabled": true, "lightEnabled": true, "rgb": true,
+                "top": [0, 0], "bottom": [100, 100],
+                "keys": [
+                    {{ "bIndex": 0, "keyCode": "Key_Escape", "top": [0, 0], "bottom": [10, 10] }},
+                    {{ "bIndex": 6, "keyCode": "{}", "top": [10, 0], "bottom": [20, 10] }}
+                ]
+            }}"#,
+            key_code
+        );
+        parse_definition_json(&json).unwrap().0
+    }
+
+    fn build(key_code: &str, unknown_keys: UnknownKeyPolicy) -> Result<Keyboard, String> {
+        build_keyboard(
+            definition(key_code),
+            KeyboardUsbId {
+                vid: 0x258a,
+                pid: 0x4f,
+            },
+            "",
+            String::new(),
+            unknown_keys,
+        )
+    }
+
+    #[test]
+    fn bundled_definitions_using_the_added_codes_load_strictly() {
+        for (pid, code) in [
+            (0xa1, KeyCode::KeyCalculator),
+            (0x103, KeyCode::KeyLeftShiftSplit),
+        ] {
+            let keyboard = load_definition(
+                0x258a,
+                pid,
+                "",
+                &bundled_root(),
+                true,
+                DefinitionOptions::STRICT,
+            )
+            .unwrap();
+
+            let key = keyboard
+                .keys
+                .iter()
+                .find(|key| key.key_code == code)
+                .unwrap();
+            assert!(key.remappable);
+        }
+    }
+
+    #[test]
+    fn unknown_key_names_are_kept_unmappable() {
+        let keyboard = build("Key_Hyper", UnknownKeyPolicy::Unmappable).unwrap();
+
+        assert_eq!(keyboard.keys.len(), 2);
+        assert!(keyboard.keys[0].remappable);
+        assert_eq!(keyboard.keys[1].buffer_index, 6);
+        assert_eq!(keyboard.keys[1].key_code, KeyCode::KeyInvalid);
+        assert!(!keyboard.keys[1].remappable);
+    }
+
+    #[test]
+    fn unknown_key_names_are_rejected_when_strict() {
+        let error = build("Key_Hyper", UnknownKeyPolicy::Reject).unwrap_err();
+
+        assert_eq!(error, "keys[1]: Unknown key code: Key_Hyper");
+    }
+
+    #[test]
+    fn key_map_definitions_without_keys_are_rejected() {
+        let root = TempRoot::new("missing-keys");
+        root.add(0xa1, "No keys");
+        let relative = definition_relative_path(0x258a, 0xa1);
+        let mut definition: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(root.0.join(&relative)).unwrap()).unwrap();
+        definition.as_object_mut().unwrap().remove("keys");
+        root.write(&relative, &definition.to_string());
+
+        let error = load_definition(0x258a, 0xa1, "", &root.0, false, DefinitionOptions::STRICT)
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::DefinitionInvalid {
+                path: root.0.join(&relative).display().to_string(),
+                message: "Missing 'keys' array".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn override_roots_take_precedence_over_the_bundled_one() {
+        let root = TempRoot::new("override-precedence");
+        root.add(0xa1, "Override");
+
+        let keyboard =
+            load_keyboard_config(0x258a, 0xa1, "", &root.roots(), DefinitionOptions::STRICT)
+                .unwrap();
+
+        assert_eq!(keyboard.name, "Override");
+        let image = Path::new(&keyboard.image_path);
+        assert!(image.is_absolute());
+        assert!(image.ends_with("258a/images/a1.png"));
+        assert!(image.starts_with(std::path::absolute(&root.0).unwrap()));
+    }
+
+    #[test]
+    fn models_missing_from_the_overrides_come_from_the_bundled_root() {
+        let root = TempRoot::new("override-fallback");
+        root.add(0xa1, "Override");
+
+        let keyboard =
+            load_keyboard_config(0x258a, 0x103, "", &root.roots(), DefinitionOptions::STRICT)
+                .unwrap();
+
+        assert_ne!(keyboard.name, "Override");
+        assert_eq!(keyboard.image_path, "keyboards/258a/images/103.png");
+    }
+
+    #[test]
+    fn override_images_are_not_taken_from_the_bundled_root() {
+        let root = TempRoot::new("override-image");
+        root.add(0xa1, "Override");
+        let image = root.0.join("258a").join("images").join("a1.png");
+        fs::remove_file(&image).unwrap();
+
+        let error =
+            load_keyboard_config(0x258a, 0xa1, "", &root.roots(), DefinitionOptions::STRICT)
+                .unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::DefinitionMissing {
+                path: image.display().to_string()
+            }
+        );
+    }
+}
@@ -1,19 +1,53 @@
 mod commands;
-mod hid;
-mod keyboard;
-mod models;
-mod modes;
-mod protocol;
+pub mod decoder;
+pub mod definition;
+pub mod delivery;
+pub mod emulator;
+pub mod error;
+pub mod hid;
+pub mod interop;
+pub mod keyboard;
+pub mod lint;
+pub mod models;
+pub mod modes;
+pub mod protocol;
+pub mod registry;
+#[cfg(test)]
+mod test_support;
+pub mod transport;
+pub mod validation;
+pub mod worker;
 
 use commands::init_hid_manager;
+use hid::{watch_definitions, DefinitionWatcher, DeviceWatcher, DEFAULT_WATCH_INTERVAL};
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize HID manager
     let hid_manager = init_hid_manager().expect("Failed to initialize HID manager");
+    let watcher_manager = hid_manager.clone();
 
     let mut builder = tauri::Builder::default()
-        .setup(|app| {
+        .setup(move |app| {
+            // Watch for keyboards being plugged in or unplugged
+            let watcher = DeviceWatcher::spawn(
+                app.handle().clone(),
+                watcher_manager.clone(),
+                DEFAULT_WATCH_INTERVAL,
+            );
+            app.manage(watcher);
+
+            // Pick up edited definitions without restarting the app, for definition authors
+            if watch_definitions() {
+                let definition_watcher = DefinitionWatcher::spawn(
+                    app.handle().clone(),
+                    watcher_manager.clone(),
+                    DEFAULT_WATCH_INTERVAL,
+                );
+                app.manage(definition_watcher);
+            }
+
             #[cfg(desktop)]
             {
                 app.handle()
@@ -138,8 +172,19 @@ pub fn run() {
         .manage(hid_manager)
         .invoke_handler(tauri::generate_handler![
             commands::scan_keyboards,
+            commands::list_supported_keyboards,
             commands::send_keyboard_config,
+            commands::send_keyboard_config_to_all,
+            commands::cancel_device_operations,
             commands::get_lighting_modes,
+            commands::list_key_codes,
+            commands::compose_key_code,
+            commands::decompose_key_code,
+            commands::key_code_names,
+            commands::key_code_from_name,
+            commands::export_key_mappings,
+            commands::import_key_mappings,
+            commands::decode_feature_reports,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
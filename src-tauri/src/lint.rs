@@ -0,0 +1,419 @@
+use crate::definition::{parse_definition_json, KeyDefinition, KeyboardDefinition, Point};
+use crate::models::KeyCode;
+use crate::protocol::KEY_MAP_KEY_COUNT;
+use crate::registry::definition_paths;
+use crate::validation::CUSTOM_LIGHT_LED_COUNT;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How serious a definition problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The definition is broken or the app would misbehave with it
+    Error,
+    /// Suspicious but harmless
+    Warning,
+}
+
+/// Problem found in a definition file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Issues of one definition file
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub issues: Vec<LintIssue>,
+}
+
+impl FileReport {
+    pub fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+}
+
+/// Check every definition file under `root` (laid out as `<vid>/configs/<pid>.json`)
+pub fn lint_definitions(root: &Path) -> Result<Vec<FileReport>, String> {
+    let paths =
+        definition_paths(root).map_err(|e| format!("Failed to read {}: {}", root.display(), e))?;
+
+    Ok(paths
+        .into_iter()
+        .map(|(path, _)| FileReport {
+            issues: lint_definition_file(&path),
+            path,
+        })
+        .collect())
+}
+
+/// Check one definition file, stored as `<vid>/configs/<pid>.json`
+pub fn lint_definition_file(path: &Path) -> Vec<LintIssue> {
+    let content = match fs::read(path) {
+        Ok(bytes) => match String::from_utf8(bytes) {
+            Ok(content) => content,
+            Err(e) => return vec![LintIssue::error(format!("File is not UTF-8: {}", e))],
+        },
+        Err(e) => return vec![LintIssue::error(format!("Failed to read file: {}", e))],
+    };
+
+    let (definition, unknown_fields) = match parse_definition_json(&content) {
+        Ok(parsed) => parsed,
+        Err(e) => return vec![LintIssue::error(e)],
+    };
+
+    let mut issues: Vec<LintIssue> = unknown_fields
+        .into_iter()
+        .map(|field| LintIssue::error(format!("Unknown field: {}", field)))
+        .collect();
+
+    check_file_name(path, &definition, &mut issues);
+    check_keys(&definition, &mut issues);
+
+    issues
+}
+
+/// The app looks definitions and images up by the lowercase hex PID
+fn check_file_name(path: &Path, definition: &KeyboardDefinition, issues: &mut Vec<LintIssue>) {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+
+    match u16::from_str_radix(stem, 16) {
+        Ok(pid) if format!("{:x}", pid) != stem => issues.push(LintIssue::error(format!(
+            "File name {} is not the lowercase hex PID, the app looks for {:x}.json",
+            stem, pid
+        ))),
+        Ok(_) => {}
+        Err(_) => issues.push(LintIssue::error(format!(
+            "File name {} is not a hex PID",
+            stem
+        ))),
+    }
+
+    if definition.pid != stem {
+        issues.push(LintIssue::error(format!(
+            "'pid' is {} but the file is named {}.json",
+            definition.pid, stem
+        )));
+    }
+
+    // keyboards/<vid>/configs/<pid>.json -> keyboards/<vid>/images/<pid>.png
+    if let Some(vendor_dir) = path.parent().and_then(Path::parent) {
+        let image = vendor_dir.join("images").join(format!("{}.png", stem));
+        if !image.exists() {
+            issues.push(LintIssue::error(format!(
+                "Missing image {}",
+                image.display()
+            )));
+        }
+    }
+}
+
+fn check_keys(definition: &KeyboardDefinition, issues: &mut Vec<LintIssue>) {
+    if is_inverted(definition.top, definition.bottom) {
+        issues.push(LintIssue::error(format!(
+            "Board bounds {:?}-{:?} are inverted",
+            definition.top, definition.bottom
+        )));
+    }
+
+    let mut seen: HashMap<u8, &str> = HashMap::new();
+    let mut placed: Vec<&KeyDefinition> = Vec::new();
+
+    for key in &definition.keys {
+        let label = format!("Key {} ({})", key.b_index, key.key_code);
+
+        if KeyCode::from_str(&key.key_code).is_none() {
+            issues.push(LintIssue::error(format!("{}: unknown key code", label)));
+        }
+
+        if let Some(other) = seen.insert(key.b_index, &key.key_code) {
+            issues.push(LintIssue::error(format!(
+                "{}: bIndex already used by {}",
+                label, other
+            )));
+        }
+
+        if definition.key_map_enabled && key.b_index as usize >= KEY_MAP_KEY_COUNT {
+            issues.push(LintIssue::error(format!(
+                "{}: the key map block only addresses {} keys",
+                label, KEY_MAP_KEY_COUNT
+            )));
+        }
+        if definition.light_enabled && key.b_index as usize >= CUSTOM_LIGHT_LED_COUNT {
+            issues.push(LintIssue::error(format!(
+                "{}: the custom light block only addresses {} LEDs",
+                label, CUSTOM_LIGHT_LED_COUNT
+            )));
+        }
+
+        if is_inverted(key.top, key.bottom) {
+            issues.push(LintIssue::error(format!(
+                "{}: rectangle {:?}-{:?} is inverted",
+                label, key.top, key.bottom
+            )));
+            continue;
+        }
+        if key.top[0] == key.bottom[0] || key.top[1] == key.bottom[1] {
+            issues.push(LintIssue::warning(format!(
+                "{}: rectangle {:?}-{:?} has no area, the key cannot be clicked",
+                label, key.top, key.bottom
+            )));
+            continue;
+        }
+
+        if !contains(definition.top, definition.bottom, key) {
+            issues.push(LintIssue::error(format!(
+                "{}: rectangle {:?}-{:?} is outside the board bounds {:?}-{:?}",
+                label, key.top, key.bottom, definition.top, definition.bottom
+            )));
+        }
+
+        for other in &placed {
+            if overlaps(key, other) {
+                issues.push(LintIssue::error(format!(
+                    "{}: rectangle overlaps key {} ({})",
+                    label, other.b_index, other.key_code
+                )));
+            }
+        }
+        placed.push(key);
+    }
+}
+
+fn is_inverted(top: Point, bottom: Point) -> bool {
+    top[0] > bottom[0] || top[1] > bottom[1]
+}
+
+fn contains(top: Point, bottom: Point, key: &KeyDefinition) -> bool {
+    key.top[0] >= top[0]
+        && key.top[1] >= top[1]
+        && key.bottom[0] <= bottom[0]
+        && key.bottom[1] <= bottom[1]
+}
+
+/// Whether two key rectangles share some area (touching edges do not count)
+fn overlaps(a: &KeyDefinition, b: &KeyDefinition) -> bool {
+    a.top[0] < b.bottom[0]
+        && b.top[0] < a.bottom[0]
+        && a.top[1] < b.bottom[1]
+        && b.top[1] < a.bottom[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bundled_root, TempRoot};
+
+    /// Key-map-only definition of a 100x100 board with the given `keys` JSON array
+    fn definition(pid: &str, keys: &str) -> KeyboardDefinition {
+        let json = format!(
+            r#"{{
+                "pid": "{}", "name": "Test", "enabled": true,
+                "keyMapEnabled": true, "lightEnabled": false,
+                "top": [0, 0], "bottom": [100, 100],
+                "keys": {}
+            }}"#,
+            pid, keys
+        );
+        parse_definition_json(&json).unwrap().0
+    }
+
+    fn key_issues(keys: &str) -> Vec<LintIssue> {
+        let mut issues = Vec::new();
+        check_keys(&definition("4f", keys), &mut issues);
+        issues
+    }
+
+    fn file_issues(path: &Path, pid: &str) -> Vec<LintIssue> {
+        let mut issues = Vec::new();
+        check_file_name(path, &definition(pid, "[]"), &mut issues);
+        issues
+    }
+
+    fn issues(light_enabled: bool, rgb: bool) -> Vec<LintIssue> {
+        let json = format!(
+            r#"{{
+                "pid": "4f", "name": "Test", "enabled": true,
+                "keyMapEnabled": false, "lightEnabled": {}, "rgb": {},
+                "top": [0, 0], "bottom": [100, 100],
+                "keys": [
+                    {{ "bIndex": 200, "keyCode": "Key_Escape", "top": [0, 0], "bottom": [10, 10] }}
+                ]
+            }}"#,
+            light_enabled, rgb
+        );
+        let (definition, _) = parse_definition_json(&json).unwrap();
+
+        let mut issues = Vec::new();
+        check_keys(&definition, &mut issues);
+        issues
+    }
+
+    #[test]
+    fn led_bound_applies_whenever_lighting_is_enabled() {
+        let expected = vec![LintIssue::error(format!(
+            "Key 200 (Key_Escape): the custom light block only addresses {} LEDs",
+            CUSTOM_LIGHT_LED_COUNT
+        ))];
+
+        assert_eq!(issues(true, false), expected);
+        assert_eq!(issues(true, true), expected);
+        assert!(issues(false, true).is_empty());
+    }
+
+    #[test]
+    fn duplicate_b_index_is_reported() {
+        let issues = key_issues(
+            r#"[
+                { "bIndex": 3, "keyCode": "Key_A", "top": [0, 0], "bottom": [10, 10] },
+                { "bIndex": 3, "keyCode": "Key_B", "top": [10, 0], "bottom": [20, 10] }
+            ]"#,
+        );
+
+        assert_eq!(
+            issues,
+            [LintIssue::error(
+                "Key 3 (Key_B): bIndex already used by Key_A".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn key_map_bound_is_reported() {
+        let issues = key_issues(&format!(
+            r#"[{{ "bIndex": {}, "keyCode": "Key_A", "top": [0, 0], "bottom": [10, 10] }}]"#,
+            KEY_MAP_KEY_COUNT
+        ));
+
+        assert_eq!(
+            issues,
+            [LintIssue::error(format!(
+                "Key {} (Key_A): the key map block only addresses {} keys",
+                KEY_MAP_KEY_COUNT, KEY_MAP_KEY_COUNT
+            ))]
+        );
+    }
+
+    #[test]
+    fn overlapping_rectangles_are_reported() {
+        let issues = key_issues(
+            r#"[
+                { "bIndex": 0, "keyCode": "Key_A", "top": [0, 0], "bottom": [10, 10] },
+                { "bIndex": 1, "keyCode": "Key_B", "top": [5, 5], "bottom": [15, 15] }
+            ]"#,
+        );
+
+        assert_eq!(
+            issues,
+            [LintIssue::error(
+                "Key 1 (Key_B): rectangle overlaps key 0 (Key_A)".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn inverted_rectangles_are_reported() {
+        let issues = key_issues(
+            r#"[{ "bIndex": 0, "keyCode": "Key_A", "top": [10, 10], "bottom": [0, 0] }]"#,
+        );
+
+        assert_eq!(
+            issues,
+            [LintIssue::error(
+                "Key 0 (Key_A): rectangle [10, 10]-[0, 0] is inverted".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn keys_outside_the_board_are_reported() {
+        let issues = key_issues(
+            r#"[{ "bIndex": 0, "keyCode": "Key_A", "top": [90, 90], "bottom": [110, 110] }]"#,
+        );
+
+        assert_eq!(
+            issues,
+            [LintIssue::error(
+                "Key 0 (Key_A): rectangle [90, 90]-[110, 110] is outside the board bounds \
+                 [0, 0]-[100, 100]"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn pid_not_matching_the_file_name_is_reported() {
+        let path = bundled_root().join("258a").join("configs").join("a1.json");
+
+        assert_eq!(
+            file_issues(&path, "4f"),
+            [LintIssue::error(
+                "'pid' is 4f but the file is named a1.json".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_image_is_reported() {
+        let root = TempRoot::new("lint-missing-image");
+        let path = root.0.join("258a").join("configs").join("4f.json");
+        let image = root.0.join("258a").join("images").join("4f.png");
+
+        assert_eq!(
+            file_issues(&path, "4f"),
+            [LintIssue::error(format!(
+                "Missing image {}",
+                image.display()
+            ))]
+        );
+    }
+
+    #[test]
+    fn definition_paths_are_shared_with_the_registry() {
+        let root = bundled_root();
+
+        let reports = lint_definitions(&root).unwrap();
+        let paths = definition_paths(&root).unwrap();
+
+        assert!(!reports.is_empty());
+        assert!(reports
+            .iter()
+            .zip(&paths)
+            .all(|(report, (path, id))| &report.path == path && id.is_some()));
+        assert_eq!(reports.len(), paths.len());
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::BitOr;
 
 /// USB Vendor ID and Product ID pair
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -7,297 +10,422 @@ pub struct KeyboardUsbId {
     pub pid: u16,
 }
 
-/// Key code enum matching the protocol codes
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u32)]
-pub enum KeyCode {
+/// Key code as written to the key mapping block.
+///
+/// The firmware accepts any 32-bit code, so this is a plain wrapper around the raw value
+/// with named constants for the codes the app knows; profiles holding codes without a
+/// name (e.g. read back from the device) survive a round trip unchanged.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCode(u32);
+
+/// Group a key code belongs to in the mapping editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyCategory {
+    Letters,
+    Numbers,
+    Symbols,
+    Special,
+    Navigation,
+    Function,
+    Modifiers,
+    Numpad,
+    Shortcuts,
+    Media,
+    Browser,
+}
+
+/// Catalogue entry of a named key code
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyCodeInfo {
+    /// Name used in keyboard definition files (e.g. `Key_A`)
+    pub name: &'static str,
+    /// Label shown in lists (e.g. `Left Bracket ([)`)
+    pub label: &'static str,
+    /// Label short enough to fit on a key cap (e.g. `[`)
+    pub short_label: &'static str,
+    pub category: KeyCategory,
+    pub value: u32,
+}
+
+/// Define the named key codes with their definition file name, category and labels
+macro_rules! key_codes {
+    ($($variant:ident = $value:expr => $name:literal, $category:ident, $label:literal, $short:literal,)*) => {
+        #[allow(non_upper_case_globals)]
+        impl KeyCode {
+            $(pub const $variant: KeyCode = KeyCode($value);)*
+        }
+
+        /// Every named key code, in catalogue order
+        const NAMED_KEY_CODES: &[KeyCodeInfo] = &[$(KeyCodeInfo {
+            name: $name,
+            label: $label,
+            short_label: $short,
+            category: KeyCategory::$category,
+            value: $value,
+        },)*];
+    };
+}
+
+key_codes! {
     // Numbers
-    Key1 = 0x1e00,
-    Key2 = 0x1f00,
-    Key3 = 0x2000,
-    Key4 = 0x2100,
-    Key5 = 0x2200,
-    Key6 = 0x2300,
-    Key7 = 0x2400,
-    Key8 = 0x2500,
-    Key9 = 0x2600,
-    Key0 = 0x2700,
+    Key1 = 0x1e00 => "Key_1", Numbers, "1", "1",
+    Key2 = 0x1f00 => "Key_2", Numbers, "2", "2",
+    Key3 = 0x2000 => "Key_3", Numbers, "3", "3",
+    Key4 = 0x2100 => "Key_4", Numbers, "4", "4",
+    Key5 = 0x2200 => "Key_5", Numbers, "5", "5",
+    Key6 = 0x2300 => "Key_6", Numbers, "6", "6",
+    Key7 = 0x2400 => "Key_7", Numbers, "7", "7",
+    Key8 = 0x2500 => "Key_8", Numbers, "8", "8",
+    Key9 = 0x2600 => "Key_9", Numbers, "9", "9",
+    Key0 = 0x2700 => "Key_0", Numbers, "0", "0",
 
     // Letters
-    KeyA = 0x0400,
-    KeyB = 0x0500,
-    KeyC = 0x0600,
-    KeyD = 0x0700,
-    KeyE = 0x0800,
-    KeyF = 0x0900,
-    KeyG = 0x0a00,
-    KeyH = 0x0b00,
-    KeyI = 0x0c00,
-    KeyJ = 0x0d00,
-    KeyK = 0x0e00,
-    KeyL = 0x0f00,
-    KeyM = 0x1000,
-    KeyN = 0x1100,
-    KeyO = 0x1200,
-    KeyP = 0x1300,
-    KeyQ = 0x1400,
-    KeyR = 0x1500,
-    KeyS = 0x1600,
-    KeyT = 0x1700,
-    KeyU = 0x1800,
-    KeyV = 0x1900,
-    KeyW = 0x1a00,
-    KeyX = 0x1b00,
-    KeyY = 0x1c00,
-    KeyZ = 0x1d00,
+    KeyA = 0x0400 => "Key_A", Letters, "A", "A",
+    KeyB = 0x0500 => "Key_B", Letters, "B", "B",
+    KeyC = 0x0600 => "Key_C", Letters, "C", "C",
+    KeyD = 0x0700 => "Key_D", Letters, "D", "D",
+    KeyE = 0x0800 => "Key_E", Letters, "E", "E",
+    KeyF = 0x0900 => "Key_F", Letters, "F", "F",
+    KeyG = 0x0a00 => "Key_G", Letters, "G", "G",
+    KeyH = 0x0b00 => "Key_H", Letters, "H", "H",
+    KeyI = 0x0c00 => "Key_I", Letters, "I", "I",
+    KeyJ = 0x0d00 => "Key_J", Letters, "J", "J",
+    KeyK = 0x0e00 => "Key_K", Letters, "K", "K",
+    KeyL = 0x0f00 => "Key_L", Letters, "L", "L",
+    KeyM = 0x1000 => "Key_M", Letters, "M", "M",
+    KeyN = 0x1100 => "Key_N", Letters, "N", "N",
+    KeyO = 0x1200 => "Key_O", Letters, "O", "O",
+    KeyP = 0x1300 => "Key_P", Letters, "P", "P",
+    KeyQ = 0x1400 => "Key_Q", Letters, "Q", "Q",
+    KeyR = 0x1500 => "Key_R", Letters, "R", "R",
+    KeyS = 0x1600 => "Key_S", Letters, "S", "S",
+    KeyT = 0x1700 => "Key_T", Letters, "T", "T",
+    KeyU = 0x1800 => "Key_U", Letters, "U", "U",
+    KeyV = 0x1900 => "Key_V", Letters, "V", "V",
+    KeyW = 0x1a00 => "Key_W", Letters, "W", "W",
+    KeyX = 0x1b00 => "Key_X", Letters, "X", "X",
+    KeyY = 0x1c00 => "Key_Y", Letters, "Y", "Y",
+    KeyZ = 0x1d00 => "Key_Z", Letters, "Z", "Z",
 
     // Symbols
-    KeyHyphen = 0x2d00,
-    KeyEquals = 0x2e00,
-    KeyLeftBracket = 0x2f00,
-    KeyRightBracket = 0x3000,
-    KeyBackSlash = 0x3100,
-    KeySemiColon = 0x3300,
-    KeyQuote = 0x3400,
-    KeyBackQuote = 0x3500,
-    KeyComma = 0x3600,
-    KeyDot = 0x3700,
-    KeySlash = 0x3800,
+    KeyHyphen = 0x2d00 => "Key_Hyphen", Symbols, "Hyphen (-)", "-",
+    KeyEquals = 0x2e00 => "Key_Equals", Symbols, "Equals (=)", "=",
+    KeyLeftBracket = 0x2f00 => "Key_Left_Bracket", Symbols, "Left Bracket ([)", "[",
+    KeyRightBracket = 0x3000 => "Key_Right_Bracket", Symbols, "Right Bracket (])", "]",
+    KeyBackSlash = 0x3100 => "Key_Back_Slash", Symbols, "Backslash (\\)", "\\",
+    KeySemiColon = 0x3300 => "Key_Semi_Colon", Symbols, "Semicolon (;)", ";",
+    KeyQuote = 0x3400 => "Key_Quote", Symbols, "Quote (')", "'",
+    KeyBackQuote = 0x3500 => "Key_Back_Quote", Symbols, "Back Quote (`)", "`",
+    KeyComma = 0x3600 => "Key_Comma", Symbols, "Comma (,)", ",",
+    KeyDot = 0x3700 => "Key_Dot", Symbols, "Dot (.)", ".",
+    KeySlash = 0x3800 => "Key_Slash", Symbols, "Slash (/)", "/",
 
     // Modifiers
-    KeyLeftControl = 0x010000,
-    KeyLeftShift = 0x020000,
-    KeyLeftAlt = 0x040000,
-    KeyRightControl = 0x100000,
-    KeyRightShift = 0x200000,
-    KeyRightAlt = 0x400000,
-    KeyLeftSuper = 0x080000,
-    KeyRightSuper = 0x800000,
+    KeyLeftControl = 0x010000 => "Key_Left_Control", Modifiers, "Left Control", "LCtrl",
+    KeyLeftShift = 0x020000 => "Key_Left_Shift", Modifiers, "Left Shift", "LShift",
+    KeyLeftAlt = 0x040000 => "Key_Left_Alt", Modifiers, "Left Alt", "LAlt",
+    KeyRightControl = 0x100000 => "Key_Right_Control", Modifiers, "Right Control", "RCtrl",
+    KeyRightShift = 0x200000 => "Key_Right_Shift", Modifiers, "Right Shift", "RShift",
+    KeyRightAlt = 0x400000 => "Key_Right_Alt", Modifiers, "Right Alt", "RAlt",
+    KeyLeftSuper = 0x080000 => "Key_Left_Super", Modifiers, "Left Super (Win)", "LWin",
+    KeyRightSuper = 0x800000 => "Key_Right_Super", Modifiers, "Right Super (Win)", "RWin",
 
     // Arrow keys
-    KeyRight = 0x4f00,
-    KeyLeft = 0x5000,
-    KeyDown = 0x5100,
-    KeyUp = 0x5200,
+    KeyRight = 0x4f00 => "Key_Right", Navigation, "Arrow Right", "→",
+    KeyLeft = 0x5000 => "Key_Left", Navigation, "Arrow Left", "←",
+    KeyDown = 0x5100 => "Key_Down", Navigation, "Arrow Down", "↓",
+    KeyUp = 0x5200 => "Key_Up", Navigation, "Arrow Up", "↑",
 
     // Function keys
-    KeyF1 = 0x3a00,
-    KeyF2 = 0x3b00,
-    KeyF3 = 0x3c00,
-    KeyF4 = 0x3d00,
-    KeyF5 = 0x3e00,
-    KeyF6 = 0x3f00,
-    KeyF7 = 0x4000,
-    KeyF8 = 0x4100,
-    KeyF9 = 0x4200,
-    KeyF10 = 0x4300,
-    KeyF11 = 0x4400,
-    KeyF12 = 0x4500,
+    KeyF1 = 0x3a00 => "Key_F1", Function, "F1", "F1",
+    KeyF2 = 0x3b00 => "Key_F2", Function, "F2", "F2",
+    KeyF3 = 0x3c00 => "Key_F3", Function, "F3", "F3",
+    KeyF4 = 0x3d00 => "Key_F4", Function, "F4", "F4",
+    KeyF5 = 0x3e00 => "Key_F5", Function, "F5", "F5",
+    KeyF6 = 0x3f00 => "Key_F6", Function, "F6", "F6",
+    KeyF7 = 0x4000 => "Key_F7", Function, "F7", "F7",
+    KeyF8 = 0x4100 => "Key_F8", Function, "F8", "F8",
+    KeyF9 = 0x4200 => "Key_F9", Function, "F9", "F9",
+    KeyF10 = 0x4300 => "Key_F10", Function, "F10", "F10",
+    KeyF11 = 0x4400 => "Key_F11", Function, "F11", "F11",
+    KeyF12 = 0x4500 => "Key_F12", Function, "F12", "F12",
 
     // Special keys
-    KeyEscape = 0x2900,
-    KeyTab = 0x2b00,
-    KeyEnter = 0x2800,
-    KeyMenu = 0x6500,
-    KeyInsert = 0x4900,
-    KeyPause = 0x4800,
-    KeySpace = 0x2c00,
-    KeyHome = 0x4a00,
-    KeyEnd = 0x4d00,
-    KeyDelete = 0x4c00,
-    KeyPageDown = 0x4e00,
-    KeyPageUp = 0x4b00,
-    KeyCapsLock = 0x3900,
-    KeyBackspace = 0x2a00,
-    KeyPrintScreen = 0x4600,
+    KeyEscape = 0x2900 => "Key_Escape", Special, "Escape", "Esc",
+    KeyTab = 0x2b00 => "Key_Tab", Special, "Tab", "Tab",
+    KeyEnter = 0x2800 => "Key_Enter", Special, "Enter", "Enter",
+    KeyMenu = 0x6500 => "Key_Menu", Special, "Menu", "Menu",
+    KeyInsert = 0x4900 => "Key_Insert", Navigation, "Insert", "Ins",
+    KeyPause = 0x4800 => "Key_Pause", Navigation, "Pause", "Pause",
+    KeySpace = 0x2c00 => "Key_Space", Special, "Space", "Space",
+    KeyHome = 0x4a00 => "Key_Home", Navigation, "Home", "Home",
+    KeyEnd = 0x4d00 => "Key_End", Navigation, "End", "End",
+    KeyDelete = 0x4c00 => "Key_Delete", Navigation, "Delete", "Del",
+    KeyPageDown = 0x4e00 => "Key_Page_Down", Navigation, "Page Down", "PgDn",
+    KeyPageUp = 0x4b00 => "Key_Page_Up", Navigation, "Page Up", "PgUp",
+    KeyCapsLock = 0x3900 => "Key_Caps_Lock", Special, "Caps Lock", "Caps",
+    KeyBackspace = 0x2a00 => "Key_Backspace", Special, "Backspace", "Backspace",
+    KeyPrintScreen = 0x4600 => "Key_Print_Screen", Special, "Print Screen", "PrtSc",
+    KeyScrollLock = 0x4700 => "Key_Scroll_Lock", Special, "Scroll Lock", "ScrLk",
+    // ISO key between the short left shift and Z
+    KeyLeftShiftSplit = 0x6400 => "Key_Left_Shift_Split", Special, "ISO Backslash (\\)", "ISO\\",
 
     // Numpad
-    KeyNum1 = 0x5900,
-    KeyNum2 = 0x5a00,
-    KeyNum3 = 0x5b00,
-    KeyNum4 = 0x5c00,
-    KeyNum5 = 0x5d00,
-    KeyNum6 = 0x5e00,
-    KeyNum7 = 0x5f00,
-    KeyNum8 = 0x6000,
-    KeyNum9 = 0x6100,
-    KeyNum0 = 0x6200,
-    KeyNumAdd = 0x5700,
-    KeyNumSubstract = 0x5600,
-    KeyNumMultiply = 0x5500,
-    KeyNumDivide = 0x5400,
-    KeyNumDecimalPoint = 0x6300,
-    KeyNumLock = 0x5300,
-    KeyNumEnter = 0x5800,
+    KeyNum1 = 0x5900 => "Key_Num_1", Numpad, "Num 1", "Num1",
+    KeyNum2 = 0x5a00 => "Key_Num_2", Numpad, "Num 2", "Num2",
+    KeyNum3 = 0x5b00 => "Key_Num_3", Numpad, "Num 3", "Num3",
+    KeyNum4 = 0x5c00 => "Key_Num_4", Numpad, "Num 4", "Num4",
+    KeyNum5 = 0x5d00 => "Key_Num_5", Numpad, "Num 5", "Num5",
+    KeyNum6 = 0x5e00 => "Key_Num_6", Numpad, "Num 6", "Num6",
+    KeyNum7 = 0x5f00 => "Key_Num_7", Numpad, "Num 7", "Num7",
+    KeyNum8 = 0x6000 => "Key_Num_8", Numpad, "Num 8", "Num8",
+    KeyNum9 = 0x6100 => "Key_Num_9", Numpad, "Num 9", "Num9",
+    KeyNum0 = 0x6200 => "Key_Num_0", Numpad, "Num 0", "Num0",
+    KeyNumAdd = 0x5700 => "Key_Num_Add", Numpad, "Num +", "Num+",
+    KeyNumSubstract = 0x5600 => "Key_Num_Substract", Numpad, "Num -", "Num-",
+    KeyNumMultiply = 0x5500 => "Key_Num_Multiply", Numpad, "Num *", "Num*",
+    KeyNumDivide = 0x5400 => "Key_Num_Divide", Numpad, "Num /", "Num/",
+    KeyNumDecimalPoint = 0x6300 => "Key_Num_Decimal_Point", Numpad, "Num .", "Num.",
+    KeyNumLock = 0x5300 => "Key_Num_Lock", Numpad, "Num Lock", "NumLock",
+    KeyNumEnter = 0x5800 => "Key_Num_Enter", Numpad, "Num Enter", "NumEnter",
 
     // Function key
-    KeyFn = 0xb000,
+    KeyFn = 0xb000 => "Key_Fn", Function, "Fn", "Fn",
 
     // Shortcuts
-    ShortcutExplorer = 0x01000194,
-    ShortcutCut = 0x011b00,
-    ShortcutCopy = 0x010600,
-    ShortcutPaste = 0x011900,
-    ShortcutSave = 0x011600,
-    ShortcutShowDesktop = 0x080700,
-    ShortcutLock = 0x080f00,
-    ShortcutSwitchWindow = 0x042b00,
-    ShortcutCloseWindow = 0x043d00,
+    ShortcutExplorer = 0x01000194 => "Shortcut_Explorer", Shortcuts, "Open Explorer", "Explorer",
+    ShortcutCut = 0x011b00 => "Shortcut_Cut", Shortcuts, "Cut (Ctrl+X)", "Cut",
+    ShortcutCopy = 0x010600 => "Shortcut_Copy", Shortcuts, "Copy (Ctrl+C)", "Copy",
+    ShortcutPaste = 0x011900 => "Shortcut_Paste", Shortcuts, "Paste (Ctrl+V)", "Paste",
+    ShortcutSave = 0x011600 => "Shortcut_Save", Shortcuts, "Save (Ctrl+S)", "Save",
+    ShortcutShowDesktop = 0x080700 => "Shortcut_Show_Desktop", Shortcuts, "Show Desktop (Win+D)", "Desktop",
+    ShortcutLock = 0x080f00 => "Shortcut_Lock", Shortcuts, "Lock (Win+L)", "Lock",
+    ShortcutSwitchWindow = 0x042b00 => "Shortcut_Switch_Window", Shortcuts, "Switch Window (Alt+Tab)", "Alt+Tab",
+    ShortcutCloseWindow = 0x043d00 => "Shortcut_Close_Window", Shortcuts, "Close Window (Alt+F4)", "Alt+F4",
 
     // Multimedia
-    MultimediaPlayPause = 0x010000cd,
-    MultimediaStop = 0x010000b7,
-    MultimediaPrevious = 0x010000b6,
-    MultimediaNext = 0x010000b5,
-    MultimediaVolumeUp = 0x010000e9,
-    MultimediaVolumeDown = 0x010000ea,
-    MultimediaMute = 0x010000e2,
+    MultimediaPlayPause = 0x010000cd => "Multimedia_Play_Pause", Media, "Play / Pause", "Play",
+    MultimediaStop = 0x010000b7 => "Multimedia_Stop", Media, "Stop", "Stop",
+    MultimediaPrevious = 0x010000b6 => "Multimedia_Previous", Media, "Previous Track", "Prev",
+    MultimediaNext = 0x010000b5 => "Multimedia_Next", Media, "Next Track", "Next",
+    MultimediaVolumeUp = 0x010000e9 => "Multimedia_Volume_Up", Media, "Volume Up", "Vol+",
+    MultimediaVolumeDown = 0x010000ea => "Multimedia_Volume_Down", Media, "Volume Down", "Vol-",
+    MultimediaMute = 0x010000e2 => "Multimedia_Mute", Media, "Mute", "Mute",
+
+    // Dedicated media keys (same codes as the multimedia functions)
+    KeyVolumeUp = 0x010000e9 => "Key_Volume_Up", Media, "Volume Up", "Vol+",
+    KeyVolumeDown = 0x010000ea => "Key_Volume_Down", Media, "Volume Down", "Vol-",
+    KeyMute = 0x010000e2 => "Key_Mute", Media, "Mute", "Mute",
+    KeyCalculator = 0x01000192 => "Key_Calculator", Media, "Calculator", "Calc",
+
+    // Consumer controls (0x01 prefix followed by the consumer page usage)
+    MultimediaBrightnessUp = 0x0100006f => "Multimedia_Brightness_Up", Media, "Brightness Up", "Bri+",
+    MultimediaBrightnessDown = 0x01000070 => "Multimedia_Brightness_Down", Media, "Brightness Down", "Bri-",
+    MultimediaMediaSelect = 0x01000183 => "Multimedia_Media_Select", Media, "Media Player", "Media",
+    ApplicationMail = 0x0100018a => "Application_Mail", Media, "Mail", "Mail",
+    BrowserSearch = 0x01000221 => "Browser_Search", Browser, "Browser Search", "Search",
+    BrowserHome = 0x01000223 => "Browser_Home", Browser, "Browser Home", "WWW",
+    BrowserBack = 0x01000224 => "Browser_Back", Browser, "Browser Back", "Back",
+    BrowserForward = 0x01000225 => "Browser_Forward", Browser, "Browser Forward", "Fwd",
+    BrowserStop = 0x01000226 => "Browser_Stop", Browser, "Browser Stop", "Stop",
+    BrowserRefresh = 0x01000227 => "Browser_Refresh", Browser, "Browser Refresh", "Reload",
+    BrowserFavorites = 0x0100022a => "Browser_Favorites", Browser, "Browser Favorites", "Fav",
+
+    // System power, sleep and wake (generic desktop page) are not listed yet: no
+    // capture shows how the firmware encodes them, unlike the consumer page above
+}
 
-    // Invalid
-    KeyInvalid = 0,
+#[allow(non_upper_case_globals)]
+impl KeyCode {
+    /// Placeholder for keys that cannot be remapped
+    pub const KeyInvalid: KeyCode = KeyCode(0);
 }
 
 impl KeyCode {
     /// Parse key code from string (e.g., "Key_A", "Key_1")
     pub fn from_str(s: &str) -> Option<KeyCode> {
-        match s {
-            "Key_1" => Some(KeyCode::Key1),
-            "Key_2" => Some(KeyCode::Key2),
-            "Key_3" => Some(KeyCode::Key3),
-            "Key_4" => Some(KeyCode::Key4),
-            "Key_5" => Some(KeyCode::Key5),
-            "Key_6" => Some(KeyCode::Key6),
-            "Key_7" => Some(KeyCode::Key7),
-            "Key_8" => Some(KeyCode::Key8),
-            "Key_9" => Some(KeyCode::Key9),
-            "Key_0" => Some(KeyCode::Key0),
-            "Key_A" => Some(KeyCode::KeyA),
-            "Key_B" => Some(KeyCode::KeyB),
-            "Key_C" => Some(KeyCode::KeyC),
-            "Key_D" => Some(KeyCode::KeyD),
-            "Key_E" => Some(KeyCode::KeyE),
-            "Key_F" => Some(KeyCode::KeyF),
-            "Key_G" => Some(KeyCode::KeyG),
-            "Key_H" => Some(KeyCode::KeyH),
-            "Key_I" => Some(KeyCode::KeyI),
-            "Key_J" => Some(KeyCode::KeyJ),
-            "Key_K" => Some(KeyCode::KeyK),
-            "Key_L" => Some(KeyCode::KeyL),
-            "Key_M" => Some(KeyCode::KeyM),
-            "Key_N" => Some(KeyCode::KeyN),
-            "Key_O" => Some(KeyCode::KeyO),
-            "Key_P" => Some(KeyCode::KeyP),
-            "Key_Q" => Some(KeyCode::KeyQ),
-            "Key_R" => Some(KeyCode::KeyR),
-            "Key_S" => Some(KeyCode::KeyS),
-            "Key_T" => Some(KeyCode::KeyT),
-            "Key_U" => Some(KeyCode::KeyU),
-            "Key_V" => Some(KeyCode::KeyV),
-            "Key_W" => Some(KeyCode::KeyW),
-            "Key_X" => Some(KeyCode::KeyX),
-            "Key_Y" => Some(KeyCode::KeyY),
-            "Key_Z" => Some(KeyCode::KeyZ),
-            "Key_Hyphen" => Some(KeyCode::KeyHyphen),
-            "Key_Equals" => Some(KeyCode::KeyEquals),
-            "Key_Left_Bracket" => Some(KeyCode::KeyLeftBracket),
-            "Key_Right_Bracket" => Some(KeyCode::KeyRightBracket),
-            "Key_Back_Slash" => Some(KeyCode::KeyBackSlash),
-            "Key_Semi_Colon" => Some(KeyCode::KeySemiColon),
-            "Key_Quote" => Some(KeyCode::KeyQuote),
-            "Key_Back_Quote" => Some(KeyCode::KeyBackQuote),
-            "Key_Comma" => Some(KeyCode::KeyComma),
-            "Key_Dot" => Some(KeyCode::KeyDot),
-            "Key_Slash" => Some(KeyCode::KeySlash),
-            "Key_Left_Control" => Some(KeyCode::KeyLeftControl),
-            "Key_Left_Shift" => Some(KeyCode::KeyLeftShift),
-            "Key_Left_Alt" => Some(KeyCode::KeyLeftAlt),
-            "Key_Right_Control" => Some(KeyCode::KeyRightControl),
-            "Key_Right_Shift" => Some(KeyCode::KeyRightShift),
-            "Key_Right_Alt" => Some(KeyCode::KeyRightAlt),
-            "Key_Left_Super" => Some(KeyCode::KeyLeftSuper),
-            "Key_Right_Super" => Some(KeyCode::KeyRightSuper),
-            "Key_Right" => Some(KeyCode::KeyRight),
-            "Key_Left" => Some(KeyCode::KeyLeft),
-            "Key_Down" => Some(KeyCode::KeyDown),
-            "Key_Up" => Some(KeyCode::KeyUp),
-            "Key_F1" => Some(KeyCode::KeyF1),
-            "Key_F2" => Some(KeyCode::KeyF2),
-            "Key_F3" => Some(KeyCode::KeyF3),
-            "Key_F4" => Some(KeyCode::KeyF4),
-            "Key_F5" => Some(KeyCode::KeyF5),
-            "Key_F6" => Some(KeyCode::KeyF6),
-            "Key_F7" => Some(KeyCode::KeyF7),
-            "Key_F8" => Some(KeyCode::KeyF8),
-            "Key_F9" => Some(KeyCode::KeyF9),
-            "Key_F10" => Some(KeyCode::KeyF10),
-            "Key_F11" => Some(KeyCode::KeyF11),
-            "Key_F12" => Some(KeyCode::KeyF12),
-            "Key_Escape" => Some(KeyCode::KeyEscape),
-            "Key_Tab" => Some(KeyCode::KeyTab),
-            "Key_Enter" => Some(KeyCode::KeyEnter),
-            "Key_Menu" => Some(KeyCode::KeyMenu),
-            "Key_Insert" => Some(KeyCode::KeyInsert),
-            "Key_Pause" => Some(KeyCode::KeyPause),
-            "Key_Space" => Some(KeyCode::KeySpace),
-            "Key_Home" => Some(KeyCode::KeyHome),
-            "Key_End" => Some(KeyCode::KeyEnd),
-            "Key_Delete" => Some(KeyCode::KeyDelete),
-            "Key_Page_Down" => Some(KeyCode::KeyPageDown),
-            "Key_Page_Up" => Some(KeyCode::KeyPageUp),
-            "Key_Caps_Lock" => Some(KeyCode::KeyCapsLock),
-            "Key_Backspace" => Some(KeyCode::KeyBackspace),
-            "Key_Print_Screen" => Some(KeyCode::KeyPrintScreen),
-            "Key_Num_1" => Some(KeyCode::KeyNum1),
-            "Key_Num_2" => Some(KeyCode::KeyNum2),
-            "Key_Num_3" => Some(KeyCode::KeyNum3),
-            "Key_Num_4" => Some(KeyCode::KeyNum4),
-            "Key_Num_5" => Some(KeyCode::KeyNum5),
-            "Key_Num_6" => Some(KeyCode::KeyNum6),
-            "Key_Num_7" => Some(KeyCode::KeyNum7),
-            "Key_Num_8" => Some(KeyCode::KeyNum8),
-            "Key_Num_9" => Some(KeyCode::KeyNum9),
-            "Key_Num_0" => Some(KeyCode::KeyNum0),
-            "Key_Num_Add" => Some(KeyCode::KeyNumAdd),
-            "Key_Num_Substract" => Some(KeyCode::KeyNumSubstract),
-            "Key_Num_Multiply" => Some(KeyCode::KeyNumMultiply),
-            "Key_Num_Divide" => Some(KeyCode::KeyNumDivide),
-            "Key_Num_Decimal_Point" => Some(KeyCode::KeyNumDecimalPoint),
-            "Key_Num_Lock" => Some(KeyCode::KeyNumLock),
-            "Key_Num_Enter" => Some(KeyCode::KeyNumEnter),
-            "Key_Fn" => Some(KeyCode::KeyFn),
-            "Shortcut_Explorer" => Some(KeyCode::ShortcutExplorer),
-            "Shortcut_Cut" => Some(KeyCode::ShortcutCut),
-            "Shortcut_Copy" => Some(KeyCode::ShortcutCopy),
-            "Shortcut_Paste" => Some(KeyCode::ShortcutPaste),
-            "Shortcut_Save" => Some(KeyCode::ShortcutSave),
-            "Shortcut_Show_Desktop" => Some(KeyCode::ShortcutShowDesktop),
-            "Shortcut_Lock" => Some(KeyCode::ShortcutLock),
-            "Shortcut_Switch_Window" => Some(KeyCode::ShortcutSwitchWindow),
-            "Shortcut_Close_Window" => Some(KeyCode::ShortcutCloseWindow),
-            "Multimedia_Play_Pause" => Some(KeyCode::MultimediaPlayPause),
-            "Multimedia_Stop" => Some(KeyCode::MultimediaStop),
-            "Multimedia_Previous" => Some(KeyCode::MultimediaPrevious),
-            "Multimedia_Next" => Some(KeyCode::MultimediaNext),
-            "Multimedia_Volume_Up" => Some(KeyCode::MultimediaVolumeUp),
-            "Multimedia_Volume_Down" => Some(KeyCode::MultimediaVolumeDown),
-            "Multimedia_Mute" => Some(KeyCode::MultimediaMute),
-            _ => None,
-        }
+        NAMED_KEY_CODES
+            .iter()
+            .find(|info| info.name == s)
+            .map(|info| KeyCode(info.value))
+    }
+
+    /// Definition file name of the code, if it is a known one
+    pub fn name(self) -> Option<&'static str> {
+        self.info().map(|info| info.name)
+    }
+
+    /// Catalogue entry of the code, if it is a known one
+    pub fn info(self) -> Option<&'static KeyCodeInfo> {
+        NAMED_KEY_CODES.iter().find(|info| info.value == self.0)
     }
 
     pub fn to_u32(self) -> u32 {
-        self as u32
+        self.0
+    }
+
+    /// Wrap a raw firmware code, whether or not it has a name
+    pub const fn from_u32(value: u32) -> KeyCode {
+        KeyCode(value)
+    }
+}
+
+/// Modifier keys of a chord, as a bit mask independent of the side.
+///
+/// Only the low nibble is used; larger values are rejected when deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const CTRL: Modifiers = Modifiers(0x01);
+    pub const SHIFT: Modifiers = Modifiers(0x02);
+    pub const ALT: Modifiers = Modifiers(0x04);
+    pub const SUPER: Modifiers = Modifiers(0x08);
+
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl TryFrom<u8> for Modifiers {
+    type Error = String;
+
+    fn try_from(bits: u8) -> Result<Self, Self::Error> {
+        if bits > 0x0f {
+            return Err(format!("invalid modifier mask: 0x{:02x}", bits));
+        }
+        Ok(Modifiers(bits))
+    }
+}
+
+impl From<Modifiers> for u8 {
+    fn from(modifiers: Modifiers) -> u8 {
+        modifiers.0
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers((self.0 | rhs.0) & 0x0f)
+    }
+}
+
+/// Which of the two modifier keys of a kind is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierSide {
+    Left,
+    Right,
+}
+
+/// A key pressed together with modifiers, e.g. Ctrl+Shift+T
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub base: KeyCode,
+    pub modifiers: Modifiers,
+    pub side: ModifierSide,
+}
+
+/// Prefix of the codes that play a macro, followed by the macro slot.
+///
+/// Unverified: no capture shows how the firmware stores macros, so key maps refuse
+/// these codes until it does.
+const MACRO_PREFIX: u32 = 0x0300_0000;
+
+impl KeyCode {
+    /// Combine a plain key with modifiers.
+    ///
+    /// The firmware encodes a chord as the HID modifier byte (left modifiers in the
+    /// low nibble, right ones in the high nibble) above the key usage, which is how
+    /// the `Shortcut_*` codes are built. Returns `None` when `base` is not a plain key
+    /// (a modifier, a chord or a consumer code).
+    pub fn with_modifiers(
+        base: KeyCode,
+        modifiers: Modifiers,
+        side: ModifierSide,
+    ) -> Option<KeyCode> {
+        base.usage()?;
+
+        let bits = match side {
+            ModifierSide::Left => modifiers.0,
+            ModifierSide::Right => modifiers.0 << 4,
+        };
+
+        Some(KeyCode(base.0 | ((bits as u32) << 16)))
+    }
+
+    /// Split a chord back into its key and modifiers.
+    ///
+    /// Returns `None` for codes that are not a plain key held with modifiers of a
+    /// single side.
+    pub fn chord(self) -> Option<KeyChord> {
+        let bits = ((self.0 >> 16) & 0xff) as u8;
+        let base = KeyCode(self.0 & 0xff00);
+
+        if self.0 & 0xff0000ff != 0 || base.0 == 0 || bits == 0 {
+            return None;
+        }
+
+        let (modifiers, side) = match (bits & 0x0f, bits >> 4) {
+            (left, 0) => (left, ModifierSide::Left),
+            (0, right) => (right, ModifierSide::Right),
+            _ => return None,
+        };
+
+        Some(KeyChord {
+            base,
+            modifiers: Modifiers(modifiers),
+            side,
+        })
+    }
+
+    /// Code of a key that plays the macro stored in `slot`
+    pub const fn macro_key(slot: u8) -> KeyCode {
+        KeyCode(MACRO_PREFIX | slot as u32)
+    }
+
+    /// Macro slot played by this code, if it is a macro key
+    pub fn macro_slot(self) -> Option<u8> {
+        (self.0 & !0xff == MACRO_PREFIX).then_some(self.0 as u8)
     }
 
-    /// Convert u32 to KeyCode
-    /// Since KeyCode uses #[repr(u32)], we can safely transmute any u32 value
-    pub fn from_u32(value: u32) -> KeyCode {
-        // This is safe because we're using #[repr(u32)] which guarantees
-        // the enum has the same memory layout as u32
-        unsafe { std::mem::transmute(value) }
+    /// HID keyboard usage of a plain key (no modifiers, not a consumer code)
+    fn usage(self) -> Option<u8> {
+        (self.0 & !0xff00 == 0 && self.0 != 0).then_some((self.0 >> 8) as u8)
     }
 }
 
+impl fmt::Debug for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "Raw(0x{:08x})", self.0),
+        }
+    }
+}
+
+/// Every named key code with its labels and category.
+///
+/// Codes listed under several names (e.g. `Key_Mute` and `Multimedia_Mute`) are
+/// returned once, under their first name.
+pub fn list_key_codes() -> Vec<KeyCodeInfo> {
+    let mut seen = HashSet::new();
+    NAMED_KEY_CODES
+        .iter()
+        .filter(|info| seen.insert(info.value))
+        .cloned()
+        .collect()
+}
+
 impl Serialize for KeyCode {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -313,7 +441,6 @@ impl<'de> Deserialize<'de> for KeyCode {
         D: serde::Deserializer<'de>,
     {
         use serde::de::{self, Visitor};
-        use std::fmt;
 
         struct KeyCodeVisitor;
 
@@ -369,6 +496,10 @@ impl<'de> Deserialize<'de> for KeyCode {
 pub struct Key {
     pub buffer_index: u8,
     pub key_code: KeyCode,
+    /// False when the definition names a code the app does not know; the key can
+    /// still be colored but not remapped
+    #[serde(default = "default_remappable")]
+    pub remappable: bool,
     pub top_x: i32,
     pub top_y: i32,
     pub bottom_x: i32,
@@ -380,18 +511,58 @@ pub struct Key {
 pub struct Keyboard {
     pub id: KeyboardUsbId,
     pub path: String,
+    /// USB serial number, used to tell identical models apart
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    /// USB interface number of the configuration interface (-1 if unknown)
+    #[serde(default = "unknown_interface_number")]
+    pub interface_number: i32,
     pub name: String,
     pub image_path: String,
     pub keys: Vec<Key>,
     pub key_map_enabled: bool,
+    /// Macro storage of the model, `None` when it cannot store macros
+    #[serde(default)]
+    pub macro_limits: Option<MacroLimits>,
     pub light_enabled: bool,
     pub rgb: bool,
+    /// Whether the firmware answers block read requests (declared by the definition)
+    #[serde(default)]
+    pub read_back: bool,
     pub top_left_x: i32,
     pub top_left_y: i32,
     pub bottom_right_x: i32,
     pub bottom_right_y: i32,
 }
 
+fn unknown_interface_number() -> i32 {
+    -1
+}
+
+fn default_remappable() -> bool {
+    true
+}
+
+/// Key map layer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyLayer {
+    /// Keys pressed on their own
+    #[default]
+    Base,
+    /// Keys pressed while holding Fn
+    Fn,
+}
+
+/// Macro storage limits of a model, declared in its definition file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroLimits {
+    /// Number of macros the firmware can store
+    pub slots: u8,
+    /// Maximum number of steps of a single macro
+    pub max_steps: u8,
+}
+
 /// Lighting mode with mode bit
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mode {
@@ -412,6 +583,9 @@ pub struct RgbColor {
 pub struct KeyboardConfig {
     pub light_mode: Option<LightModeConfig>,
     pub key_mapping: Option<KeyMappingConfig>,
+    /// Macros indexed by slot; validated but rejected, the storage format is not known yet
+    #[serde(default)]
+    pub macros: Option<Vec<Macro>>,
 }
 
 /// Light mode configuration
@@ -436,12 +610,166 @@ pub struct PerKeyColor {
 /// Key mapping configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyMappingConfig {
+    /// Mappings of every layer, each tagged with the layer it applies to
     pub mappings: Vec<KeyMapping>,
 }
 
+impl KeyMappingConfig {
+    /// Mappings that apply to `layer`
+    pub fn layer(&self, layer: KeyLayer) -> impl Iterator<Item = &KeyMapping> {
+        self.mappings
+            .iter()
+            .filter(move |mapping| mapping.layer == layer)
+    }
+}
+
 /// Single key mapping
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyMapping {
     pub buffer_index: u8,
     pub key_code: KeyCode,
+    #[serde(default)]
+    pub layer: KeyLayer,
+}
+
+/// Key event of a macro step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroEvent {
+    KeyDown,
+    KeyUp,
+}
+
+/// Single step of a macro
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroStep {
+    pub event: MacroEvent,
+    pub key_code: KeyCode,
+    /// Time to wait after the event
+    #[serde(default)]
+    pub delay_ms: u16,
+}
+
+/// Sequence of key events played by a macro key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    /// Display name; not stored on the device
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_raw_codes_survive_a_round_trip() {
+        let code: KeyCode = serde_json::from_str("305419896").unwrap();
+
+        assert_eq!(code.to_u32(), 0x1234_5678);
+        assert_eq!(code.name(), None);
+        assert_eq!(format!("{:?}", code), "Raw(0x12345678)");
+        assert_eq!(serde_json::to_string(&code).unwrap(), "305419896");
+    }
+
+    #[test]
+    fn named_codes_deserialize_from_numbers_and_names() {
+        let by_value: KeyCode = serde_json::from_str(&KeyCode::KeyA.to_u32().to_string()).unwrap();
+        let by_name: KeyCode = serde_json::from_str("\"Key_A\"").unwrap();
+
+        assert_eq!(by_value, KeyCode::KeyA);
+        assert_eq!(by_name, KeyCode::KeyA);
+        assert_eq!(format!("{:?}", by_value), "Key_A");
+    }
+
+    #[test]
+    fn out_of_range_codes_are_rejected() {
+        assert!(serde_json::from_str::<KeyCode>("-1").is_err());
+        assert!(serde_json::from_str::<KeyCode>("4294967296").is_err());
+        assert!(serde_json::from_str::<KeyCode>("\"Key_Nope\"").is_err());
+    }
+
+    #[test]
+    fn catalogue_lists_every_code_once_under_its_first_name() {
+        let codes = list_key_codes();
+
+        let values: HashSet<u32> = codes.iter().map(|info| info.value).collect();
+        assert_eq!(values.len(), codes.len());
+        assert!(codes.iter().any(|info| info.name == "Multimedia_Mute"));
+        assert!(!codes.iter().any(|info| info.name == "Key_Mute"));
+        assert_eq!(KeyCode::from_str("Key_Mute"), Some(KeyCode::MultimediaMute));
+    }
+
+    #[test]
+    fn catalogue_entries_resolve_to_their_code() {
+        for info in list_key_codes() {
+            let code = KeyCode::from_str(info.name).unwrap();
+
+            assert_eq!(code.to_u32(), info.value);
+            assert_eq!(code.info().unwrap().category, info.category);
+            assert!(!info.label.is_empty() && !info.short_label.is_empty());
+        }
+
+        let escape = KeyCode::KeyEscape.info().unwrap();
+        assert_eq!(
+            (escape.name, escape.category),
+            ("Key_Escape", KeyCategory::Special)
+        );
+    }
+
+    #[test]
+    fn chords_compose_like_the_shortcut_codes() {
+        let copy = KeyCode::with_modifiers(KeyCode::KeyC, Modifiers::CTRL, ModifierSide::Left);
+        let desktop = KeyCode::with_modifiers(KeyCode::KeyD, Modifiers::SUPER, ModifierSide::Left);
+
+        assert_eq!(copy, Some(KeyCode::ShortcutCopy));
+        assert_eq!(desktop, Some(KeyCode::ShortcutShowDesktop));
+    }
+
+    #[test]
+    fn chords_split_back_into_key_and_modifiers() {
+        let modifiers = Modifiers::CTRL | Modifiers::SHIFT;
+        for side in [ModifierSide::Left, ModifierSide::Right] {
+            let code = KeyCode::with_modifiers(KeyCode::KeyT, modifiers, side).unwrap();
+
+            assert_eq!(
+                code.chord(),
+                Some(KeyChord {
+                    base: KeyCode::KeyT,
+                    modifiers,
+                    side,
+                })
+            );
+        }
+
+        let right = KeyCode::with_modifiers(KeyCode::KeyT, Modifiers::ALT, ModifierSide::Right);
+        assert_eq!(right.unwrap().to_u32(), 0x40_1700);
+    }
+
+    #[test]
+    fn only_plain_keys_form_chords() {
+        assert_eq!(
+            KeyCode::with_modifiers(KeyCode::KeyVolumeUp, Modifiers::CTRL, ModifierSide::Left),
+            None
+        );
+        assert_eq!(
+            KeyCode::with_modifiers(KeyCode::ShortcutCopy, Modifiers::SHIFT, ModifierSide::Left),
+            None
+        );
+        assert_eq!(KeyCode::KeyT.chord(), None);
+        assert_eq!(KeyCode::KeyVolumeUp.chord(), None);
+        // Modifiers of both sides at once
+        assert_eq!(KeyCode::from_u32(0x11_1700).chord(), None);
+    }
+
+    #[test]
+    fn modifier_masks_above_the_low_nibble_are_rejected() {
+        let modifiers: Modifiers = serde_json::from_str("15").unwrap();
+
+        assert!(modifiers.contains(Modifiers::CTRL | Modifiers::SUPER));
+        assert_eq!(serde_json::to_string(&modifiers).unwrap(), "15");
+        assert!(serde_json::from_str::<Modifiers>("16").is_err());
+        assert!(Modifiers::try_from(0x80).is_err());
+    }
 }
@@ -1,47 +1,178 @@
-use crate::models::{KeyCode, KeyMappingConfig, Keyboard, KeyboardConfig, LightModeConfig};
+use crate::error::AppError;
+use crate::models::{
+    KeyCode, KeyLayer, KeyMappingConfig, Keyboard, KeyboardConfig, LightModeConfig, Macro,
+};
 use crate::modes::is_custom_mode;
+use serde::Serialize;
+
+pub const BUFFER_SIZE: usize = 65;
+pub const KEY_MAP_BUFFERS_SIZE: usize = 9;
+pub const CUSTOM_LIGHT_MODE_BUFFERS_SIZE: usize = 7;
+
+/// Report ID of every configuration feature report
+pub const REPORT_ID: u8 = 0x0a;
+/// Command byte of the standard light mode packet
+pub const STANDARD_LIGHT_COMMAND: u8 = 0x29;
+/// Command byte of the first custom (per-key) light packet
+pub const CUSTOM_LIGHT_COMMAND: u8 = 0x7e;
+/// Command byte of the first key mapping packet
+pub const KEY_MAP_COMMAND: u8 = 0xf8;
+
+/// Offset of the payload in the first custom light packet
+pub const CUSTOM_LIGHT_DATA_OFFSET: usize = 6;
+/// Offset of the payload in the first key mapping packet
+pub const KEY_MAP_DATA_OFFSET: usize = 5;
+/// Number of keys the key map block has room for (4 bytes each)
+pub const KEY_MAP_KEY_COUNT: usize = (KEY_MAP_BUFFERS_SIZE * BUFFER_SIZE
+    - KEY_MAP_DATA_OFFSET
+    - (KEY_MAP_BUFFERS_SIZE - 1) * CONTINUATION_DATA_OFFSET)
+    / 4;
+/// Offset of the payload in every continuation packet
+pub const CONTINUATION_DATA_OFFSET: usize = 3;
+/// Flag set in the sub-command byte to ask the firmware to return a block instead of writing it
+/// (unverified, see `build_read_request`)
+pub const READ_REQUEST_FLAG: u8 = 0x80;
+
+/// Kind of multi-packet transfer, identified by the command bytes of its first packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    StandardLight,
+    CustomLight,
+    KeyMap,
+}
+
+impl TransferKind {
+    /// Number of packets the encoder produces for this transfer
+    pub fn packet_count(self) -> usize {
+        match self {
+            TransferKind::StandardLight => 1,
+            TransferKind::CustomLight => CUSTOM_LIGHT_MODE_BUFFERS_SIZE,
+            TransferKind::KeyMap => KEY_MAP_BUFFERS_SIZE,
+        }
+    }
 
-const BUFFER_SIZE: usize = 65;
-const KEY_MAP_BUFFERS_SIZE: usize = 9;
-const CUSTOM_LIGHT_MODE_BUFFERS_SIZE: usize = 7;
+    /// Sub-command and command bytes (offsets 3 and 4) of the first packet
+    pub fn header(self) -> (u8, u8) {
+        match self {
+            TransferKind::StandardLight => (0x02, STANDARD_LIGHT_COMMAND),
+            TransferKind::CustomLight => (0x03, CUSTOM_LIGHT_COMMAND),
+            TransferKind::KeyMap => (0x01, KEY_MAP_COMMAND),
+        }
+    }
+
+    /// Identify a transfer from the sub-command and command bytes of its first packet
+    pub fn from_header(sub_command: u8, command: u8) -> Option<TransferKind> {
+        [
+            TransferKind::StandardLight,
+            TransferKind::CustomLight,
+            TransferKind::KeyMap,
+        ]
+        .into_iter()
+        .find(|kind| kind.header() == (sub_command, command))
+    }
+}
+
+/// Reports of one transfer; the firmware only applies a transfer once all of them arrived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferGroup {
+    pub kind: TransferKind,
+    pub reports: Vec<Vec<u8>>,
+}
 
 /// Build all protocol buffers for a keyboard configuration
-pub fn build_buffers(keyboard: &Keyboard, config: &KeyboardConfig) -> Result<Vec<Vec<u8>>, String> {
-    let mut buffers = Vec::new();
+pub fn build_buffers(
+    keyboard: &Keyboard,
+    config: &KeyboardConfig,
+) -> Result<Vec<Vec<u8>>, AppError> {
+    Ok(build_transfer_groups(keyboard, config)?
+        .into_iter()
+        .flat_map(|group| group.reports)
+        .collect())
+}
+
+/// Build the protocol buffers for a keyboard configuration, grouped by transfer
+pub fn build_transfer_groups(
+    keyboard: &Keyboard,
+    config: &KeyboardConfig,
+) -> Result<Vec<TransferGroup>, AppError> {
+    let mut groups = Vec::new();
 
     // Always send standard light buffer
     if let Some(light_config) = &config.light_mode {
         let standard_buffer = build_standard_light_buffer(light_config)?;
-        buffers.push(standard_buffer);
+        groups.push(TransferGroup {
+            kind: TransferKind::StandardLight,
+            reports: vec![standard_buffer],
+        });
 
         // If custom light mode, send custom light buffers
         let is_custom = is_custom_mode(light_config.mode_bit, keyboard.rgb);
         if is_custom && light_config.custom_colors.is_some() {
             let custom_buffers = build_custom_light_buffers(keyboard, light_config)?;
-            buffers.extend(custom_buffers);
+            groups.push(TransferGroup {
+                kind: TransferKind::CustomLight,
+                reports: custom_buffers,
+            });
         }
     }
 
+    // No capture shows how the firmware stores macros, so they are checked but not sent
+    if let Some(macros) = &config.macros {
+        validate_macros(keyboard, macros)?;
+        return Err(AppError::InvalidConfig(
+            "Macros cannot be written: the macro storage format is not known yet".to_string(),
+        ));
+    }
+
     // If key mapping is enabled, send key mapping buffers
     if keyboard.key_map_enabled {
         if let Some(key_mapping) = &config.key_mapping {
+            // Macro keys would point at macros the app cannot store
+            if let Some((mapping, slot)) = key_mapping
+                .mappings
+                .iter()
+                .find_map(|mapping| Some((mapping, mapping.key_code.macro_slot()?)))
+            {
+                return Err(AppError::InvalidConfig(format!(
+                    "Key at index {} plays macro {}, which cannot be stored yet",
+                    mapping.buffer_index, slot
+                )));
+            }
+
+            // No capture shows how the firmware stores the Fn layer, so only the base
+            // layer can be written
+            if let Some(mapping) = key_mapping
+                .mappings
+                .iter()
+                .find(|mapping| mapping.layer != KeyLayer::Base)
+            {
+                return Err(AppError::InvalidConfig(format!(
+                    "The {:?} layer cannot be written (mapping for index {})",
+                    mapping.layer, mapping.buffer_index
+                )));
+            }
+
             let mapping_buffers = build_key_mapping_buffers(keyboard, key_mapping)?;
-            buffers.extend(mapping_buffers);
+            groups.push(TransferGroup {
+                kind: TransferKind::KeyMap,
+                reports: mapping_buffers,
+            });
         }
     }
 
-    Ok(buffers)
+    Ok(groups)
 }
 
 /// Build standard light mode buffer (65 bytes)
-fn build_standard_light_buffer(config: &LightModeConfig) -> Result<Vec<u8>, String> {
+fn build_standard_light_buffer(config: &LightModeConfig) -> Result<Vec<u8>, AppError> {
     let mut buffer = vec![0u8; BUFFER_SIZE];
 
-    buffer[0] = 0x0a;
+    buffer[0] = REPORT_ID;
     buffer[1] = 0x01;
     buffer[2] = 0x01;
     buffer[3] = 0x02;
-    buffer[4] = 0x29;
+    buffer[4] = STANDARD_LIGHT_COMMAND;
     buffer[5] = config.mode_bit;
     buffer[7] = config.animation;
     buffer[8] = config.brightness;
@@ -62,11 +193,11 @@ fn build_standard_light_buffer(config: &LightModeConfig) -> Result<Vec<u8>, Stri
 fn build_custom_light_buffers(
     _keyboard: &Keyboard,
     config: &LightModeConfig,
-) -> Result<Vec<Vec<u8>>, String> {
+) -> Result<Vec<Vec<u8>>, AppError> {
     let custom_colors = config
         .custom_colors
         .as_ref()
-        .ok_or("Custom colors not provided")?;
+        .ok_or_else(|| AppError::InvalidConfig("Custom colors not provided".to_string()))?;
 
     // Create full buffer for all RGB data (7 * 65 bytes)
     let mut led_full_buffer = vec![0u8; CUSTOM_LIGHT_MODE_BUFFERS_SIZE * BUFFER_SIZE];
@@ -89,18 +220,22 @@ fn build_custom_light_buffers(
     for i in 0..CUSTOM_LIGHT_MODE_BUFFERS_SIZE {
         let mut buffer = vec![0u8; BUFFER_SIZE];
 
-        buffer[0] = 0x0a;
+        buffer[0] = REPORT_ID;
         buffer[1] = CUSTOM_LIGHT_MODE_BUFFERS_SIZE as u8;
         buffer[2] = (i + 1) as u8;
 
         if i == 0 {
             buffer[3] = 0x03;
-            buffer[4] = 0x7e;
+            buffer[4] = CUSTOM_LIGHT_COMMAND;
             buffer[5] = 0x01;
         }
 
         // Copy RGB data into buffer
-        let start_index = if i == 0 { 6 } else { 3 };
+        let start_index = if i == 0 {
+            CUSTOM_LIGHT_DATA_OFFSET
+        } else {
+            CONTINUATION_DATA_OFFSET
+        };
         for buffer_index in start_index..BUFFER_SIZE {
             if led_buffer_index < led_full_buffer.len() {
                 buffer[buffer_index] = led_full_buffer[led_buffer_index];
@@ -114,11 +249,20 @@ fn build_custom_light_buffers(
     Ok(buffers)
 }
 
-/// Build key mapping buffers (9 buffers of 65 bytes each)
+/// Build key mapping buffers of the base layer (9 buffers of 65 bytes each)
 fn build_key_mapping_buffers(
     keyboard: &Keyboard,
     config: &KeyMappingConfig,
-) -> Result<Vec<Vec<u8>>, String> {
+) -> Result<Vec<Vec<u8>>, AppError> {
+    // The whole block is rewritten, so a key whose default code is unknown would lose
+    // its function on the device
+    if let Some(key) = keyboard.keys.iter().find(|key| !key.remappable) {
+        return Err(AppError::InvalidConfig(format!(
+            "Key map cannot be written: the key at index {} has an unknown default code",
+            key.buffer_index
+        )));
+    }
+
     // Create full buffer for all key mappings (9 * 65 bytes)
     let mut map_full_buffer = vec![0u8; KEY_MAP_BUFFERS_SIZE * BUFFER_SIZE];
 
@@ -133,7 +277,7 @@ fn build_key_mapping_buffers(
     }
 
     // Then apply custom mappings
-    for mapping in &config.mappings {
+    for mapping in config.layer(KeyLayer::Base) {
         let buffer_index = mapping.buffer_index as usize;
         let map_index = buffer_index * 4;
 
@@ -144,21 +288,26 @@ fn build_key_mapping_buffers(
 
     let mut buffers = Vec::new();
     let mut map_buffer_index = 0;
+    let (sub_command, command) = TransferKind::KeyMap.header();
 
     for i in 0..KEY_MAP_BUFFERS_SIZE {
         let mut buffer = vec![0u8; BUFFER_SIZE];
 
-        buffer[0] = 0x0a;
+        buffer[0] = REPORT_ID;
         buffer[1] = KEY_MAP_BUFFERS_SIZE as u8;
         buffer[2] = (i + 1) as u8;
 
         if i == 0 {
-            buffer[3] = 0x01;
-            buffer[4] = 0xf8;
+            buffer[3] = sub_command;
+            buffer[4] = command;
         }
 
         // Copy key mapping data into buffer
-        let start_index = if i == 0 { 5 } else { 3 };
+        let start_index = if i == 0 {
+            KEY_MAP_DATA_OFFSET
+        } else {
+            CONTINUATION_DATA_OFFSET
+        };
         for buffer_index in start_index..BUFFER_SIZE {
             if map_buffer_index < map_full_buffer.len() {
                 buffer[buffer_index] = map_full_buffer[map_buffer_index];
@@ -172,6 +321,50 @@ fn build_key_mapping_buffers(
     Ok(buffers)
 }
 
+/// Check macros against the storage limits of the model
+pub fn validate_macros(keyboard: &Keyboard, macros: &[Macro]) -> Result<(), AppError> {
+    let limits = keyboard
+        .macro_limits
+        .ok_or_else(|| AppError::InvalidConfig(format!("{} cannot store macros", keyboard.name)))?;
+
+    if macros.len() > limits.slots as usize {
+        return Err(AppError::InvalidConfig(format!(
+            "{} macros defined, {} stores at most {}",
+            macros.len(),
+            keyboard.name,
+            limits.slots
+        )));
+    }
+
+    for (slot, macro_) in macros.iter().enumerate() {
+        if macro_.steps.is_empty() {
+            return Err(AppError::InvalidConfig(format!(
+                "Macro {} has no steps",
+                slot
+            )));
+        }
+        if macro_.steps.len() > limits.max_steps as usize {
+            return Err(AppError::InvalidConfig(format!(
+                "Macro {} has {} steps, {} allows at most {}",
+                slot,
+                macro_.steps.len(),
+                keyboard.name,
+                limits.max_steps
+            )));
+        }
+        if let Some(step) = macro_.steps.iter().find(|step| {
+            step.key_code == KeyCode::KeyInvalid || step.key_code.macro_slot().is_some()
+        }) {
+            return Err(AppError::InvalidConfig(format!(
+                "Macro {} uses key code {:?}, which cannot be played by a macro",
+                slot, step.key_code
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 /// Set key code in buffer (4 bytes, big-endian)
 fn set_buffer_key(buffer: &mut [u8], key_code: KeyCode) {
     let key_code_u32 = key_code.to_u32();
@@ -198,3 +391,173 @@ fn set_buffer_key(buffer: &mut [u8], key_code: KeyCode) {
         buffer[3] = (key_code_u32 & 0xff) as u8;
     }
 }
+
+/// Build the request asking the firmware to return a block through `get_feature_report`.
+///
+/// The request mirrors the first packet of a write with `READ_REQUEST_FLAG` set in the
+/// sub-command byte; firmware that supports it answers with the same frames it accepts
+/// for the block, one per `get_feature_report` call.
+///
+/// Unverified: neither Rangoli nor the captures of the official software it was built
+/// from include a read, so this format is an assumption. It is only sent to models whose
+/// definition sets `readBack` (`Keyboard::read_back`).
+pub fn build_read_request(kind: TransferKind) -> Vec<u8> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let (sub_command, command) = kind.header();
+
+    buffer[0] = REPORT_ID;
+    buffer[1] = 0x01;
+    buffer[2] = 0x01;
+    buffer[3] = sub_command | READ_REQUEST_FLAG;
+    buffer[4] = command;
+
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{KeyMapping, MacroEvent, MacroLimits, MacroStep};
+    use crate::test_support::{self, key};
+
+    fn keyboard() -> Keyboard {
+        test_support::keyboard(vec![key(0, KeyCode::KeyEscape), key(6, KeyCode::KeyA)])
+    }
+
+    fn key_map_config(mappings: Vec<KeyMapping>) -> KeyboardConfig {
+        KeyboardConfig {
+            light_mode: None,
+            key_mapping: Some(KeyMappingConfig { mappings }),
+            macros: None,
+        }
+    }
+
+    fn mapping(buffer_index: u8, key_code: KeyCode, layer: KeyLayer) -> KeyMapping {
+        KeyMapping {
+            buffer_index,
+            key_code,
+            layer,
+        }
+    }
+
+    fn kinds(groups: &[TransferGroup]) -> Vec<TransferKind> {
+        groups.iter().map(|group| group.kind).collect()
+    }
+
+    #[test]
+    fn base_layer_is_written_as_one_key_map_block() {
+        let config = key_map_config(vec![mapping(0, KeyCode::KeyA, KeyLayer::Base)]);
+
+        let groups = build_transfer_groups(&keyboard(), &config).unwrap();
+
+        assert_eq!(kinds(&groups), [TransferKind::KeyMap]);
+        let block = &groups[0].reports;
+        assert_eq!(block.len(), KEY_MAP_BUFFERS_SIZE);
+        assert_eq!((block[0][3], block[0][4]), (0x01, KEY_MAP_COMMAND));
+    }
+
+    #[test]
+    fn fn_layer_mappings_are_rejected() {
+        let config = key_map_config(vec![
+            mapping(0, KeyCode::KeyA, KeyLayer::Base),
+            mapping(6, KeyCode::KeyEscape, KeyLayer::Fn),
+        ]);
+
+        let error = build_transfer_groups(&keyboard(), &config).unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::InvalidConfig(
+                "The Fn layer cannot be written (mapping for index 6)".to_string()
+            )
+        );
+    }
+
+    fn tap(key_code: KeyCode, delay_ms: u16) -> Vec<MacroStep> {
+        vec![
+            MacroStep {
+                event: MacroEvent::KeyDown,
+                key_code,
+                delay_ms,
+            },
+            MacroStep {
+                event: MacroEvent::KeyUp,
+                key_code,
+                delay_ms: 0,
+            },
+        ]
+    }
+
+    fn macro_config(steps: Vec<MacroStep>) -> KeyboardConfig {
+        KeyboardConfig {
+            light_mode: None,
+            key_mapping: None,
+            macros: Some(vec![Macro {
+                name: "Tap A".to_string(),
+                steps,
+            }]),
+        }
+    }
+
+    fn macro_keyboard() -> Keyboard {
+        Keyboard {
+            macro_limits: Some(MacroLimits {
+                slots: 2,
+                max_steps: 2,
+            }),
+            ..keyboard()
+        }
+    }
+
+    #[test]
+    fn macros_are_rejected_for_models_that_do_not_declare_them() {
+        let error =
+            build_transfer_groups(&keyboard(), &macro_config(tap(KeyCode::KeyA, 0))).unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::InvalidConfig("Test cannot store macros".to_string())
+        );
+    }
+
+    #[test]
+    fn macros_beyond_the_declared_limits_are_rejected() {
+        let mut steps = tap(KeyCode::KeyA, 0);
+        steps.extend(tap(KeyCode::KeyB, 0));
+
+        let error = build_transfer_groups(&macro_keyboard(), &macro_config(steps)).unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::InvalidConfig("Macro 0 has 4 steps, Test allows at most 2".to_string())
+        );
+    }
+
+    #[test]
+    fn valid_macros_are_not_sent() {
+        let config = macro_config(tap(KeyCode::KeyA, 10));
+
+        let error = build_transfer_groups(&macro_keyboard(), &config).unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::InvalidConfig(
+                "Macros cannot be written: the macro storage format is not known yet".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn macro_keys_are_rejected() {
+        let config = key_map_config(vec![mapping(6, KeyCode::macro_key(1), KeyLayer::Base)]);
+
+        let error = build_transfer_groups(&macro_keyboard(), &config).unwrap_err();
+
+        assert_eq!(
+            error,
+            AppError::InvalidConfig(
+                "Key at index 6 plays macro 1, which cannot be stored yet".to_string()
+            )
+        );
+    }
+}
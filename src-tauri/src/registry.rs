@@ -0,0 +1,376 @@
+use crate::error::AppError;
+use crate::keyboard::{
+    definition_relative_path, load_definition, DefinitionOptions, DefinitionRoots,
+};
+use crate::models::{Keyboard, KeyboardUsbId};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Keyboard model the app has a definition for
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportedKeyboard {
+    pub id: KeyboardUsbId,
+    pub name: String,
+    pub image_path: String,
+    pub key_map_enabled: bool,
+    pub light_enabled: bool,
+    pub rgb: bool,
+    /// Definition file the model was loaded from
+    pub definition_path: String,
+    /// Whether the definition ships with the app rather than coming from a user directory
+    pub bundled: bool,
+}
+
+/// Every model the app can configure, and the definitions that could not be loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportedKeyboards {
+    pub keyboards: Vec<SupportedKeyboard>,
+    pub failures: Vec<AppError>,
+}
+
+/// New state of a definition after its files changed on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct DefinitionChange {
+    pub id: KeyboardUsbId,
+    /// Layout now used for the model (with an empty `path`), `None` when it is no
+    /// longer supported
+    pub keyboard: Option<Keyboard>,
+    /// Why the definition could not be loaded, if it exists but is broken
+    pub error: Option<AppError>,
+}
+
+/// Loaded definition; its `path` is filled in once a device is found
+#[derive(Debug, Clone)]
+struct RegistryEntry {
+    keyboard: Keyboard,
+    definition_path: PathBuf,
+    bundled: bool,
+}
+
+/// Keyboard definitions of every root, parsed once and indexed by USB ID.
+///
+/// When several roots define the same model, the highest priority one wins even if
+/// it fails to load, so a broken override is reported rather than silently ignored.
+#[derive(Debug, Clone)]
+pub struct DefinitionRegistry {
+    roots: DefinitionRoots,
+    entries: HashMap<KeyboardUsbId, RegistryEntry>,
+    failures: HashMap<KeyboardUsbId, AppError>,
+    /// Files the app never looks up because of their name
+    misnamed: Vec<AppError>,
+}
+
+impl DefinitionRegistry {
+    /// Load every enabled definition of `roots`, logging the ones that fail
+    pub fn load(roots: DefinitionRoots) -> Self {
+        let mut registry = Self {
+            roots,
+            entries: HashMap::new(),
+            failures: HashMap::new(),
+            misnamed: Vec::new(),
+        };
+        let mut seen = HashSet::new();
+
+        for (root, bundled) in registry.roots.clone().iter() {
+            for (id, path) in definition_files(root, &mut registry.misnamed) {
+                // Skip the models a higher priority root already defines
+                if seen.insert(id) {
+                    registry.load_entry(id, root, bundled, path);
+                }
+            }
+        }
+
+        for failure in &registry.misnamed {
+            eprintln!(
+                "[RK-Configurator] Skipping keyboard definition: {}",
+                failure
+            );
+        }
+
+        registry
+    }
+
+    /// Re-read the definition of `id` from the highest priority root that has one
+    pub fn reload(&mut self, id: KeyboardUsbId) -> DefinitionChange {
+        self.entries.remove(&id);
+        self.failures.remove(&id);
+
+        let relative = definition_relative_path(id.vid, id.pid);
+        let roots = self.roots.clone();
+        if let Some((root, bundled)) = roots
+            .iter()
+            .find(|(root, _)| root.join(&relative).is_file())
+        {
+            self.load_entry(id, root, bundled, root.join(&relative));
+        }
+
+        DefinitionChange {
+            id,
+            keyboard: self.keyboard(id, ""),
+            error: self.failures.get(&id).cloned(),
+        }
+    }
+
+    /// Look the roots over again for files not named after a VID/PID
+    pub fn reload_misnamed(&mut self) {
+        self.misnamed.clear();
+        for (root, _) in self.roots.clone().iter() {
+            definition_files(root, &mut self.misnamed);
+        }
+    }
+
+    fn load_entry(&mut self, id: KeyboardUsbId, root: &Path, bundled: bool, path: PathBuf) {
+        match load_definition(
+            id.vid,
+            id.pid,
+            "",
+            root,
+            bundled,
+            DefinitionOptions::LENIENT,
+        ) {
+            Ok(keyboard) => {
+                self.entries.insert(
+                    id,
+                    RegistryEntry {
+                        keyboard,
+                        definition_path: path,
+                        bundled,
+                    },
+                );
+            }
+            // Disabled models are simply not offered
+            Err(AppError::KeyboardDisabled { .. }) => {}
+            Err(e) => {
+                eprintln!("[RK-Configurator] Skipping keyboard definition: {}", e);
+                self.failures.insert(id, e);
+            }
+        }
+    }
+
+    pub fn roots(&self) -> &DefinitionRoots {
+        &self.roots
+    }
+
+    /// Use `roots` for the next reloads; the definitions already loaded are kept until
+    /// their files are reloaded
+    pub fn set_roots(&mut self, roots: DefinitionRoots) {
+        self.roots = roots;
+    }
+
+    /// Layout of the model `id`, for the device at `device_path`
+    pub fn keyboard(&self, id: KeyboardUsbId, device_path: &str) -> Option<Keyboard> {
+        self.entries.get(&id).map(|entry| Keyboard {
+            path: device_path.to_string(),
+            ..entry.keyboard.clone()
+        })
+    }
+
+    /// Every loaded model sorted by USB ID, with the load failures
+    pub fn supported_keyboards(&self) -> SupportedKeyboards {
+        let mut keyboards: Vec<SupportedKeyboard> = self
+            .entries
+            .values()
+            .map(|entry| SupportedKeyboard {
+                id: entry.keyboard.id,
+                name: entry.keyboard.name.clone(),
+                image_path: entry.keyboard.image_path.clone(),
+                key_map_enabled: entry.keyboard.key_map_enabled,
+                light_enabled: entry.keyboard.light_enabled,
+                rgb: entry.keyboard.rgb,
+                definition_path: entry.definition_path.display().to_string(),
+                bundled: entry.bundled,
+            })
+            .collect();
+        keyboards.sort_by_key(|keyboard| (keyboard.id.vid, keyboard.id.pid));
+
+        let mut failures: Vec<(&KeyboardUsbId, &AppError)> = self.failures.iter().collect();
+        failures.sort_by_key(|(id, _)| (id.vid, id.pid));
+
+        SupportedKeyboards {
+            keyboards,
+            failures: self
+                .misnamed
+                .iter()
+                .chain(failures.into_iter().map(|(_, e)| e))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Files in the `<vid>/<dir>` directories of `root`, for each of `dirs`
+pub fn vendor_files(root: &Path, dirs: &[&str]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for vendor in fs::read_dir(root)?.flatten() {
+        for dir in dirs {
+            let Ok(entries) = fs::read_dir(vendor.path().join(dir)) else {
+                continue;
+            };
+            files.extend(entries.flatten().map(|entry| entry.path()));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Definition files of `root` (`<vid>/configs/*.json`) sorted by path, each with the
+/// model it is named after (`None` when the app would never look it up)
+pub fn definition_paths(root: &Path) -> io::Result<Vec<(PathBuf, Option<KeyboardUsbId>)>> {
+    let mut paths: Vec<PathBuf> = vendor_files(root, &["configs"])?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let id = definition_file_id(&path);
+            (path, id)
+        })
+        .collect())
+}
+
+/// Definition files of one root, with the misnamed ones reported in `failures`
+fn definition_files(root: &Path, failures: &mut Vec<AppError>) -> Vec<(KeyboardUsbId, PathBuf)> {
+    let mut files = Vec::new();
+
+    for (path, id) in definition_paths(root).unwrap_or_default() {
+        match id {
+            Some(id) => files.push((id, path)),
+            None => failures.push(AppError::DefinitionInvalid {
+                path: path.display().to_string(),
+                message: "Not named after a lowercase hex VID/PID, e.g. 258a/configs/52.json"
+                    .to_string(),
+            }),
+        }
+    }
+
+    files
+}
+
+/// Modification time of every definition file and image of `roots`, to notice edits
+pub fn definition_stamps(roots: &DefinitionRoots) -> HashMap<PathBuf, SystemTime> {
+    let mut stamps = HashMap::new();
+
+    for (root, _) in roots.iter() {
+        for path in vendor_files(root, &["configs", "images"]).unwrap_or_default() {
+            if let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) {
+                stamps.insert(path, modified);
+            }
+        }
+    }
+
+    stamps
+}
+
+/// Model a definition file or image belongs to (`<vid>/configs/<pid>.json` or
+/// `<vid>/images/<pid>.png`), `None` when it is not named after a VID/PID
+pub fn definition_file_id(path: &Path) -> Option<KeyboardUsbId> {
+    let dir = path.parent()?;
+    let vendor = dir.parent()?;
+    let expected = match dir.file_name()?.to_str()? {
+        "configs" => "json",
+        "images" => "png",
+        _ => return None,
+    };
+    if path.extension()? != expected {
+        return None;
+    }
+
+    Some(KeyboardUsbId {
+        vid: parse_hex_id(vendor.file_name()?.to_str()?)?,
+        pid: parse_hex_id(path.file_stem()?.to_str()?)?,
+    })
+}
+
+/// Parse an ID written the way the app looks it up (lowercase hex, no leading zeros)
+fn parse_hex_id(name: &str) -> Option<u16> {
+    u16::from_str_radix(name, 16)
+        .ok()
+        .filter(|id| format!("{:x}", id) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempRoot;
+
+    const A1: KeyboardUsbId = KeyboardUsbId {
+        vid: 0x258a,
+        pid: 0xa1,
+    };
+
+    fn supported(registry: &DefinitionRegistry, id: KeyboardUsbId) -> Vec<SupportedKeyboard> {
+        registry
+            .supported_keyboards()
+            .keyboards
+            .into_iter()
+            .filter(|keyboard| keyboard.id == id)
+            .collect()
+    }
+
+    #[test]
+    fn override_definition_replaces_the_bundled_one() {
+        let root = TempRoot::new("registry-override");
+        root.add(0xa1, "Override");
+
+        let registry = DefinitionRegistry::load(root.roots());
+
+        assert_eq!(registry.keyboard(A1, "").unwrap().name, "Override");
+        let listed = supported(&registry, A1);
+        assert_eq!(listed.len(), 1);
+        assert!(!listed[0].bundled);
+        assert!(Path::new(&listed[0].definition_path).starts_with(&root.0));
+
+        // Models the override root lacks are still offered from the bundled one
+        let bundled = KeyboardUsbId {
+            vid: 0x258a,
+            pid: 0x103,
+        };
+        assert!(supported(&registry, bundled)[0].bundled);
+    }
+
+    #[test]
+    fn broken_override_is_reported_instead_of_falling_back() {
+        let root = TempRoot::new("registry-broken");
+        let relative = definition_relative_path(A1.vid, A1.pid);
+        root.write(&relative, "{");
+
+        let registry = DefinitionRegistry::load(root.roots());
+
+        assert!(registry.keyboard(A1, "").is_none());
+        assert!(supported(&registry, A1).is_empty());
+        let path = root.0.join(&relative).display().to_string();
+        let failures = registry.supported_keyboards().failures;
+        assert!(failures.iter().any(|failure| matches!(
+            failure,
+            AppError::DefinitionInvalid { path: failed, .. } if *failed == path
+        )));
+    }
+
+    #[test]
+    fn misnamed_override_files_are_reported() {
+        let root = TempRoot::new("registry-misnamed");
+        let misnamed = Path::new("258a").join("configs").join("0A1.json");
+        root.write(&misnamed, "{}");
+
+        let registry = DefinitionRegistry::load(root.roots());
+
+        assert_eq!(
+            registry.supported_keyboards().failures[0],
+            AppError::DefinitionInvalid {
+                path: root.0.join(&misnamed).display().to_string(),
+                message: "Not named after a lowercase hex VID/PID, e.g. 258a/configs/52.json"
+                    .to_string(),
+            }
+        );
+        // The bundled definition of the model it was meant to replace is kept
+        assert!(supported(&registry, A1)[0].bundled);
+    }
+}
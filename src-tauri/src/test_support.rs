@@ -0,0 +1,143 @@
+//! Fixtures shared by the unit tests
+
+use crate::delivery::DeliveryOptions;
+use crate::hid::HidManager;
+use crate::keyboard::{definition_relative_path, DefinitionRoots};
+use crate::models::{Key, KeyCode, Keyboard, KeyboardUsbId};
+use crate::registry::DefinitionRegistry;
+use crate::transport::{DeviceInfo, MockTransport};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Remappable key at `buffer_index`, with an empty rectangle
+pub fn key(buffer_index: u8, key_code: KeyCode) -> Key {
+    Key {
+        buffer_index,
+        key_code,
+        remappable: true,
+        top_x: 0,
+        top_y: 0,
+        bottom_x: 0,
+        bottom_y: 0,
+    }
+}
+
+/// RGB model 258a:4f with lighting and a base layer key map, holding `keys`
+pub fn keyboard(keys: Vec<Key>) -> Keyboard {
+    Keyboard {
+        id: KeyboardUsbId {
+            vid: 0x258a,
+            pid: 0x4f,
+        },
+        path: String::new(),
+        serial_number: None,
+        interface_number: -1,
+        name: "Test".to_string(),
+        image_path: String::new(),
+        keys,
+        key_map_enabled: true,
+        macro_limits: None,
+        light_enabled: true,
+        rgb: true,
+        read_back: false,
+        top_left_x: 0,
+        top_left_y: 0,
+        bottom_right_x: 0,
+        bottom_right_y: 0,
+    }
+}
+
+/// Configuration interface of a 258a:4f keyboard at `path`
+pub fn device_info(path: &str) -> DeviceInfo {
+    DeviceInfo {
+        path: path.to_string(),
+        vendor_id: 0x258a,
+        product_id: 0x4f,
+        usage_page: 0x0001,
+        usage: 0x0080,
+        serial_number: None,
+        interface_number: 1,
+    }
+}
+
+/// Default delivery options without the pause between retries
+pub fn options() -> DeliveryOptions {
+    DeliveryOptions {
+        retry_backoff_ms: 0,
+        ..Default::default()
+    }
+}
+
+/// Definitions shipped with the app
+pub fn bundled_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("keyboards")
+}
+
+/// The bundled definitions alone, without override roots
+pub fn bundled_roots() -> DefinitionRoots {
+    DefinitionRoots {
+        overrides: Vec::new(),
+        bundled: bundled_root(),
+    }
+}
+
+/// HID manager on top of `transport`, with the bundled definitions
+pub fn manager(transport: &MockTransport) -> HidManager {
+    HidManager::with_transport(
+        Box::new(transport.clone()),
+        DefinitionRegistry::load(bundled_roots()),
+    )
+}
+
+/// Definitions root in the temp directory, removed when dropped
+pub struct TempRoot(pub PathBuf);
+
+impl TempRoot {
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("rk-configurator-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Copy of the bundled 258a definition `pid` (and its image) renamed to `name`
+    pub fn add(&self, pid: u16, name: &str) {
+        let mut definition: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(bundled_root().join(definition_relative_path(0x258a, pid)))
+                .unwrap(),
+        )
+        .unwrap();
+        definition["name"] = name.into();
+        self.write(
+            &definition_relative_path(0x258a, pid),
+            &definition.to_string(),
+        );
+
+        let image = Path::new("258a")
+            .join("images")
+            .join(format!("{:x}.png", pid));
+        fs::create_dir_all(self.0.join("258a").join("images")).unwrap();
+        fs::copy(bundled_root().join(&image), self.0.join(&image)).unwrap();
+    }
+
+    pub fn write(&self, relative: &Path, content: &str) {
+        let path = self.0.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// This root ahead of the bundled one
+    pub fn roots(&self) -> DefinitionRoots {
+        DefinitionRoots {
+            overrides: vec![self.0.clone()],
+            bundled: bundled_root(),
+        }
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
@@ -0,0 +1,431 @@
+use crate::emulator::EmulatedKeyboard;
+use crate::error::AppError;
+use hidapi::{HidApi, HidDevice};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::sync::{Arc, Mutex};
+
+/// Information about an enumerated HID interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub usage_page: u16,
+    pub usage: u16,
+    pub serial_number: Option<String>,
+    pub interface_number: i32,
+}
+
+/// Source of HID devices (enumeration and opening)
+pub trait HidTransport: Send {
+    /// Refresh and return the list of connected HID interfaces
+    fn enumerate(&mut self) -> Result<Vec<DeviceInfo>, AppError>;
+
+    /// Open the HID interface at the given path
+    fn open(&self, path: &str) -> Result<Box<dyn HidConnection>, AppError>;
+}
+
+/// An open HID interface that exchanges feature reports
+pub trait HidConnection: Send {
+    /// Send a feature report (first byte is the report ID)
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), AppError>;
+
+    /// Read a feature report into `buffer` (first byte must hold the report ID)
+    fn get_feature_report(&self, buffer: &mut [u8]) -> Result<usize, AppError>;
+}
+
+/// Transport backed by the system HID API
+pub struct HidapiTransport {
+    api: HidApi,
+}
+
+impl HidapiTransport {
+    /// Initialize the system HID API
+    pub fn new() -> Result<Self, AppError> {
+        let api = HidApi::new().map_err(|e| AppError::HidUnavailable(e.to_string()))?;
+
+        Ok(Self { api })
+    }
+}
+
+impl HidTransport for HidapiTransport {
+    fn enumerate(&mut self) -> Result<Vec<DeviceInfo>, AppError> {
+        self.api
+            .refresh_devices()
+            .map_err(|e| AppError::DeviceIo(format!("Failed to refresh HID devices: {}", e)))?;
+
+        Ok(self
+            .api
+            .device_list()
+            .map(|info| DeviceInfo {
+                path: info.path().to_string_lossy().to_string(),
+                vendor_id: info.vendor_id(),
+                product_id: info.product_id(),
+                usage_page: info.usage_page(),
+                usage: info.usage(),
+                serial_number: info.serial_number().map(str::to_string),
+                interface_number: info.interface_number(),
+            })
+            .collect())
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn HidConnection>, AppError> {
+        // Convert string path to CString for hidapi
+        let c_path = CString::new(path).map_err(|_| AppError::DeviceNotFound {
+            path: path.to_string(),
+        })?;
+
+        let device = self
+            .api
+            .open_path(c_path.as_c_str())
+            .map_err(|e| AppError::from_hid_message(path, e.to_string()))?;
+
+        Ok(Box::new(HidapiConnection {
+            device,
+            path: path.to_string(),
+        }))
+    }
+}
+
+/// Connection to a device opened through hidapi
+struct HidapiConnection {
+    device: HidDevice,
+    path: String,
+}
+
+impl HidConnection for HidapiConnection {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), AppError> {
+        self.device
+            .send_feature_report(data)
+            .map_err(|e| AppError::from_hid_message(&self.path, e.to_string()))
+    }
+
+    fn get_feature_report(&self, buffer: &mut [u8]) -> Result<usize, AppError> {
+        self.device
+            .get_feature_report(buffer)
+            .map_err(|e| AppError::from_hid_message(&self.path, e.to_string()))
+    }
+}
+
+/// In-memory device used by `MockTransport`, recording every feature report it receives
+#[derive(Clone)]
+pub struct MockDevice {
+    info: DeviceInfo,
+    state: Arc<Mutex<MockDeviceState>>,
+    emulator: Option<EmulatedKeyboard>,
+}
+
+#[derive(Default)]
+struct MockDeviceState {
+    sent_reports: Vec<Vec<u8>>,
+    responses: Vec<Vec<u8>>,
+    connected: bool,
+    failures_remaining: usize,
+}
+
+impl MockDevice {
+    /// Create a connected mock device answering to the given descriptor
+    pub fn new(info: DeviceInfo) -> Self {
+        Self {
+            info,
+            state: Arc::new(Mutex::new(MockDeviceState {
+                connected: true,
+                ..Default::default()
+            })),
+            emulator: None,
+        }
+    }
+
+    /// Create a mock device that also forwards every report to an emulated keyboard
+    pub fn with_emulator(info: DeviceInfo, emulator: EmulatedKeyboard) -> Self {
+        Self {
+            emulator: Some(emulator),
+            ..Self::new(info)
+        }
+    }
+
+    /// Emulated keyboard attached to this device, if any
+    pub fn emulator(&self) -> Option<&EmulatedKeyboard> {
+        self.emulator.as_ref()
+    }
+
+    /// Descriptor returned by enumeration
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Every feature report sent to the device, in order
+    pub fn sent_reports(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().sent_reports.clone()
+    }
+
+    /// Forget the recorded reports
+    pub fn clear_sent_reports(&self) {
+        self.state.lock().unwrap().sent_reports.clear();
+    }
+
+    /// Queue a report to be returned by the next `get_feature_report` call
+    pub fn push_response(&self, report: Vec<u8>) {
+        self.state.lock().unwrap().responses.push(report);
+    }
+
+    /// Make the next `count` feature reports fail, to simulate a flaky connection
+    pub fn fail_next_reports(&self, count: usize) {
+        self.state.lock().unwrap().failures_remaining = count;
+    }
+
+    /// Simulate plugging or unplugging the device
+    pub fn set_connected(&self, connected: bool) {
+        self.state.lock().unwrap().connected = connected;
+    }
+
+    fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().connected
+    }
+}
+
+impl HidConnection for MockDevice {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), AppError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.connected {
+            return Err(AppError::DeviceDisconnected {
+                path: self.info.path.clone(),
+            });
+        }
+
+        if state.failures_remaining > 0 {
+            state.failures_remaining -= 1;
+            return Err(AppError::DeviceIo("Simulated transfer failure".to_string()));
+        }
+
+        state.sent_reports.push(data.to_vec());
+        drop(state);
+
+        if let Some(emulator) = &self.emulator {
+            emulator.send_feature_report(data)?;
+        }
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buffer: &mut [u8]) -> Result<usize, AppError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.connected {
+            return Err(AppError::DeviceDisconnected {
+                path: self.info.path.clone(),
+            });
+        }
+        if state.responses.is_empty() {
+            drop(state);
+            return match &self.emulator {
+                Some(emulator) => emulator.get_feature_report(buffer),
+                None => Err(AppError::DeviceIo(
+                    "No feature report available".to_string(),
+                )),
+            };
+        }
+
+        let report = state.responses.remove(0);
+        let len = report.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+}
+
+/// In-memory transport for exercising the HID stack without hardware
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    devices: Arc<Mutex<HashMap<String, MockDevice>>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a device to the transport
+    pub fn add_device(&self, device: MockDevice) {
+        self.devices
+            .lock()
+            .unwrap()
+            .insert(device.info.path.clone(), device);
+    }
+
+    /// Detach the device at the given path
+    pub fn remove_device(&self, path: &str) -> Option<MockDevice> {
+        self.devices.lock().unwrap().remove(path)
+    }
+
+    /// Look up an attached device by path
+    pub fn device(&self, path: &str) -> Option<MockDevice> {
+        self.devices.lock().unwrap().get(path).cloned()
+    }
+}
+
+impl HidTransport for MockTransport {
+    fn enumerate(&mut self) -> Result<Vec<DeviceInfo>, AppError> {
+        let devices = self.devices.lock().unwrap();
+        let mut infos: Vec<DeviceInfo> = devices
+            .values()
+            .filter(|device| device.is_connected())
+            .map(|device| device.info.clone())
+            .collect();
+        infos.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(infos)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn HidConnection>, AppError> {
+        match self.device(path) {
+            Some(device) if device.is_connected() => Ok(Box::new(device)),
+            _ => Err(AppError::DeviceNotFound {
+                path: path.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::models::{
+        KeyCode, KeyLayer, KeyMapping, KeyMappingConfig, KeyboardConfig, LightModeConfig,
+        PerKeyColor, RgbColor,
+    };
+    use crate::protocol::{build_transfer_groups, TransferKind};
+    use crate::test_support::{device_info, manager, options};
+
+    /// Path of a bundled model (G87RGB, with per-key RGB and a key map)
+    const PATH: &str = "mock-g87";
+
+    fn config() -> KeyboardConfig {
+        KeyboardConfig {
+            light_mode: Some(LightModeConfig {
+                mode_bit: 0,
+                animation: 3,
+                brightness: 4,
+                color: Some(RgbColor { r: 1, g: 2, b: 3 }),
+                random_colors: false,
+                sleep: 2,
+                custom_colors: Some(vec![PerKeyColor {
+                    buffer_index: 12,
+                    color: RgbColor {
+                        r: 0xff,
+                        g: 0x80,
+                        b: 0x00,
+                    },
+                }]),
+            }),
+            key_mapping: Some(KeyMappingConfig {
+                mappings: vec![KeyMapping {
+                    buffer_index: 0,
+                    key_code: KeyCode::KeyA,
+                    layer: KeyLayer::Base,
+                }],
+            }),
+            macros: None,
+        }
+    }
+
+    #[test]
+    fn sent_config_reaches_emulated_keyboard() {
+        let transport = MockTransport::new();
+        let emulator = EmulatedKeyboard::new();
+        transport.add_device(MockDevice::with_emulator(
+            device_info(PATH),
+            emulator.clone(),
+        ));
+        let manager = manager(&transport);
+
+        let keyboard = manager.scan_keyboards().unwrap().remove(0);
+        let report = manager
+            .send_config(PATH, &keyboard, &config(), &options())
+            .unwrap();
+
+        let kinds: Vec<TransferKind> = report.groups.iter().map(|group| group.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TransferKind::StandardLight,
+                TransferKind::CustomLight,
+                TransferKind::KeyMap
+            ]
+        );
+
+        // Frames on the wire are exactly the encoded groups, in order
+        let expected: Vec<Vec<u8>> = build_transfer_groups(&keyboard, &config())
+            .unwrap()
+            .into_iter()
+            .flat_map(|group| group.reports)
+            .collect();
+        assert_eq!(transport.device(PATH).unwrap().sent_reports(), expected);
+
+        assert!(emulator.errors().is_empty());
+        assert_eq!(emulator.completed_transfers(), 3);
+        assert!(!emulator.has_pending_transfer());
+
+        let light = emulator.light_mode().unwrap();
+        assert_eq!(
+            (
+                light.mode_bit,
+                light.animation,
+                light.brightness,
+                light.sleep
+            ),
+            (0, 3, 4, 2)
+        );
+
+        let colors = emulator.custom_colors().unwrap();
+        assert_eq!(
+            (colors[12].r, colors[12].g, colors[12].b),
+            (0xff, 0x80, 0x00)
+        );
+        assert_eq!((colors[0].r, colors[0].g, colors[0].b), (0, 0, 0));
+
+        let key_map = emulator.key_map().unwrap();
+        assert_eq!(key_map[0], KeyCode::KeyA.to_u32());
+        assert_eq!(key_map[12], KeyCode::KeyF1.to_u32());
+    }
+
+    #[test]
+    fn failing_device_reports_the_group_and_sends_nothing() {
+        let transport = MockTransport::new();
+        let emulator = EmulatedKeyboard::new();
+        let device = MockDevice::with_emulator(device_info(PATH), emulator.clone());
+        transport.add_device(device.clone());
+        let manager = manager(&transport);
+
+        let keyboard = manager.scan_keyboards().unwrap().remove(0);
+        device.fail_next_reports(usize::MAX);
+        let error = manager
+            .send_config(PATH, &keyboard, &config(), &options())
+            .unwrap_err();
+
+        assert_eq!(error.group_index, 0);
+        assert_eq!(error.kind, Some(TransferKind::StandardLight));
+        assert_eq!(error.report_index, Some(0));
+        assert_eq!(error.code, ErrorCode::DeviceIo);
+        assert!(error.delivered.is_empty());
+        assert!(device.sent_reports().is_empty());
+        assert_eq!(emulator.completed_transfers(), 0);
+    }
+
+    #[test]
+    fn unplugged_device_is_not_enumerated_or_opened() {
+        let transport = MockTransport::new();
+        let device = MockDevice::new(device_info(PATH));
+        transport.add_device(device.clone());
+        let manager = manager(&transport);
+
+        let keyboard = manager.scan_keyboards().unwrap().remove(0);
+        device.set_connected(false);
+
+        assert!(manager.scan_keyboards().unwrap().is_empty());
+        let error = manager
+            .send_config(PATH, &keyboard, &config(), &options())
+            .unwrap_err();
+        assert_eq!(error.kind, None);
+        assert_eq!(error.code, ErrorCode::DeviceNotFound);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::error::AppError;
+use crate::models::{Keyboard, KeyboardConfig, LightModeConfig};
+use crate::modes::{get_rgb_modes, get_single_color_modes, is_custom_mode};
+use crate::protocol::{
+    BUFFER_SIZE, CONTINUATION_DATA_OFFSET, CUSTOM_LIGHT_DATA_OFFSET, CUSTOM_LIGHT_MODE_BUFFERS_SIZE,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+/// Brightness levels accepted by the firmware (0 turns the backlight off)
+pub const BRIGHTNESS_RANGE: RangeInclusive<u8> = 0..=5;
+/// Animation speeds accepted by the firmware
+pub const ANIMATION_RANGE: RangeInclusive<u8> = 1..=5;
+/// Sleep timer settings accepted by the firmware
+pub const SLEEP_RANGE: RangeInclusive<u8> = 1..=5;
+/// Number of LEDs the custom light block has room for (3 bytes each)
+pub const CUSTOM_LIGHT_LED_COUNT: usize = (CUSTOM_LIGHT_MODE_BUFFERS_SIZE * BUFFER_SIZE
+    - CUSTOM_LIGHT_DATA_OFFSET
+    - (CUSTOM_LIGHT_MODE_BUFFERS_SIZE - 1) * CONTINUATION_DATA_OFFSET)
+    / 3;
+
+/// What to do with a value the firmware does not accept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationPolicy {
+    /// Refuse to send the configuration
+    #[default]
+    Reject,
+    /// Clamp out-of-range values and drop per-key colors of unknown LEDs, with a warning each
+    Clamp,
+}
+
+/// Check a configuration against the target keyboard before it is encoded.
+///
+/// Returns the configuration to send, adjusted under `ValidationPolicy::Clamp`, along
+/// with a warning for every adjustment. An unknown lighting mode is always rejected
+/// since there is no sensible value to fall back to.
+pub fn validate_config(
+    keyboard: &Keyboard,
+    config: &KeyboardConfig,
+    policy: ValidationPolicy,
+) -> Result<(KeyboardConfig, Vec<String>), AppError> {
+    let mut warnings = Vec::new();
+    let mut config = config.clone();
+
+    if let Some(light_config) = config.light_mode.as_mut() {
+        validate_light_mode(keyboard, light_config, policy, &mut warnings)?;
+    }
+
+    Ok((config, warnings))
+}
+
+/// Validate a lighting configuration in place
+fn validate_light_mode(
+    keyboard: &Keyboard,
+    config: &mut LightModeConfig,
+    policy: ValidationPolicy,
+    warnings: &mut Vec<String>,
+) -> Result<(), AppError> {
+    let modes = if keyboard.rgb {
+        get_rgb_modes()
+    } else {
+        get_single_color_modes()
+    };
+    if !modes.iter().any(|mode| mode.mode_bit == config.mode_bit) {
+        return Err(AppError::InvalidConfig(format!(
+            "Unknown {} lighting mode {}",
+            if keyboard.rgb { "RGB" } else { "single-color" },
+            config.mode_bit
+        )));
+    }
+
+    config.brightness = check_range(
+        "brightness",
+        config.brightness,
+        BRIGHTNESS_RANGE,
+        policy,
+        warnings,
+    )?;
+    config.animation = check_range(
+        "animation",
+        config.animation,
+        ANIMATION_RANGE,
+        policy,
+        warnings,
+    )?;
+    config.sleep = check_range("sleep", config.sleep, SLEEP_RANGE, policy, warnings)?;
+
+    let Some(custom_colors) = config.custom_colors.take() else {
+        return Ok(());
+    };
+
+    if !is_custom_mode(config.mode_bit, keyboard.rgb) {
+        warnings.push(format!(
+            "Per-key colors are ignored in lighting mode {}",
+            config.mode_bit
+        ));
+    }
+
+    // Keys are only listed by definitions with a key map; otherwise any LED the
+    // block has room for is accepted
+    let key_indices: HashSet<u8> = keyboard.keys.iter().map(|key| key.buffer_index).collect();
+    let mut seen = HashSet::new();
+    let mut colors = Vec::with_capacity(custom_colors.len());
+
+    for per_key in custom_colors {
+        let index = per_key.buffer_index;
+        let problem = if index as usize >= CUSTOM_LIGHT_LED_COUNT {
+            Some(format!(
+                "Per-key color index {} is beyond the {} LEDs of the custom light block",
+                index, CUSTOM_LIGHT_LED_COUNT
+            ))
+        } else if !key_indices.is_empty() && !key_indices.contains(&index) {
+            Some(format!(
+                "Per-key color index {} is not a key of {}",
+                index, keyboard.name
+            ))
+        } else {
+            None
+        };
+
+        match (problem, policy) {
+            (Some(message), ValidationPolicy::Reject) => {
+                return Err(AppError::InvalidConfig(message))
+            }
+            (Some(message), ValidationPolicy::Clamp) => {
+                warnings.push(format!("{}, dropped", message));
+            }
+            (None, _) => {
+                if !seen.insert(index) {
+                    warnings.push(format!(
+                        "Per-key color index {} is set more than once, the last color is used",
+                        index
+                    ));
+                }
+                colors.push(per_key);
+            }
+        }
+    }
+
+    config.custom_colors = Some(colors);
+    Ok(())
+}
+
+/// Check that `value` is in `range`, clamping it under `ValidationPolicy::Clamp`
+fn check_range(
+    field: &str,
+    value: u8,
+    range: RangeInclusive<u8>,
+    policy: ValidationPolicy,
+    warnings: &mut Vec<String>,
+) -> Result<u8, AppError> {
+    if range.contains(&value) {
+        return Ok(value);
+    }
+
+    let message = format!(
+        "{} {} is outside {}-{}",
+        field,
+        value,
+        range.start(),
+        range.end()
+    );
+
+    match policy {
+        ValidationPolicy::Reject => Err(AppError::InvalidConfig(message)),
+        ValidationPolicy::Clamp => {
+            let clamped = value.clamp(*range.start(), *range.end());
+            warnings.push(format!("{}, clamped to {}", message, clamped));
+            Ok(clamped)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{KeyCode, PerKeyColor, RgbColor};
+    use crate::modes::{RgbMode, SingleColorMode};
+    use crate::test_support::{key, keyboard};
+
+    fn color(buffer_index: u8) -> PerKeyColor {
+        PerKeyColor {
+            buffer_index,
+            color: RgbColor { r: 1, g: 2, b: 3 },
+        }
+    }
+
+    fn config(mode_bit: u8, brightness: u8, animation: u8, sleep: u8) -> KeyboardConfig {
+        KeyboardConfig {
+            light_mode: Some(LightModeConfig {
+                mode_bit,
+                animation,
+                brightness,
+                color: None,
+                random_colors: false,
+                sleep,
+                custom_colors: None,
+            }),
+            key_mapping: None,
+            macros: None,
+        }
+    }
+
+    fn with_colors(mut config: KeyboardConfig, colors: Vec<PerKeyColor>) -> KeyboardConfig {
+        if let Some(light_mode) = config.light_mode.as_mut() {
+            light_mode.custom_colors = Some(colors);
+        }
+        config
+    }
+
+    fn light_mode(config: &KeyboardConfig) -> &LightModeConfig {
+        config.light_mode.as_ref().unwrap()
+    }
+
+    #[test]
+    fn range_bounds_are_accepted() {
+        let keyboard = keyboard(Vec::new());
+        let mode = RgbMode::NeonStream as u8;
+
+        for (brightness, animation, sleep) in [(0, 1, 1), (5, 5, 5)] {
+            let (validated, warnings) = validate_config(
+                &keyboard,
+                &config(mode, brightness, animation, sleep),
+                ValidationPolicy::Reject,
+            )
+            .unwrap();
+
+            assert!(warnings.is_empty());
+            assert_eq!(light_mode(&validated).brightness, brightness);
+        }
+    }
+
+    #[test]
+    fn values_outside_the_ranges_are_rejected() {
+        let keyboard = keyboard(Vec::new());
+        let mode = RgbMode::NeonStream as u8;
+
+        for config in [
+            config(mode, 6, 3, 3),
+            config(mode, 3, 0, 3),
+            config(mode, 3, 6, 3),
+            config(mode, 3, 3, 0),
+            config(mode, 3, 3, 6),
+        ] {
+            let result = validate_config(&keyboard, &config, ValidationPolicy::Reject);
+
+            assert!(matches!(result, Err(AppError::InvalidConfig(_))));
+        }
+    }
+
+    #[test]
+    fn values_outside_the_ranges_are_clamped_with_a_warning() {
+        let keyboard = keyboard(Vec::new());
+
+        let (validated, warnings) = validate_config(
+            &keyboard,
+            &config(RgbMode::NeonStream as u8, 9, 0, 200),
+            ValidationPolicy::Clamp,
+        )
+        .unwrap();
+
+        let light_mode = light_mode(&validated);
+        assert_eq!(
+            (
+                light_mode.brightness,
+                light_mode.animation,
+                light_mode.sleep
+            ),
+            (5, 1, 5)
+        );
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(warnings[0], "brightness 9 is outside 0-5, clamped to 5");
+    }
+
+    #[test]
+    fn modes_are_checked_against_the_keyboard_type() {
+        let rgb = keyboard(Vec::new());
+        let single_color = Keyboard {
+            rgb: false,
+            ..keyboard(Vec::new())
+        };
+        let steady = config(SingleColorMode::Steady as u8, 3, 3, 3);
+        let unknown = config(200, 3, 3, 3);
+
+        assert!(validate_config(&single_color, &steady, ValidationPolicy::Reject).is_ok());
+        // Unknown modes are rejected even when clamping
+        assert!(validate_config(&rgb, &unknown, ValidationPolicy::Clamp).is_err());
+        assert!(validate_config(&single_color, &unknown, ValidationPolicy::Clamp).is_err());
+    }
+
+    #[test]
+    fn per_key_colors_must_address_a_key() {
+        let keyboard = keyboard(vec![key(0, KeyCode::KeyA), key(6, KeyCode::KeyA)]);
+        let config = with_colors(
+            config(RgbMode::Custom as u8, 3, 3, 3),
+            vec![color(0), color(7), color(6)],
+        );
+
+        assert!(validate_config(&keyboard, &config, ValidationPolicy::Reject).is_err());
+
+        let (validated, warnings) =
+            validate_config(&keyboard, &config, ValidationPolicy::Clamp).unwrap();
+        let indices: Vec<u8> = light_mode(&validated)
+            .custom_colors
+            .iter()
+            .flatten()
+            .map(|color| color.buffer_index)
+            .collect();
+        assert_eq!(indices, [0, 6]);
+        assert_eq!(
+            warnings,
+            ["Per-key color index 7 is not a key of Test, dropped"]
+        );
+    }
+
+    #[test]
+    fn per_key_colors_beyond_the_block_are_rejected_without_a_key_list() {
+        let keyboard = keyboard(Vec::new());
+        let last = (CUSTOM_LIGHT_LED_COUNT - 1) as u8;
+        let config =
+            |index| with_colors(config(RgbMode::Custom as u8, 3, 3, 3), vec![color(index)]);
+
+        assert!(validate_config(&keyboard, &config(last), ValidationPolicy::Reject).is_ok());
+        assert!(validate_config(&keyboard, &config(last + 1), ValidationPolicy::Reject).is_err());
+    }
+
+    #[test]
+    fn duplicate_and_ignored_per_key_colors_are_reported() {
+        let keyboard = keyboard(vec![key(0, KeyCode::KeyA)]);
+        let config = with_colors(
+            config(RgbMode::NeonStream as u8, 3, 3, 3),
+            vec![color(0), color(0)],
+        );
+
+        let (_, warnings) = validate_config(&keyboard, &config, ValidationPolicy::Reject).unwrap();
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Per-key colors are ignored"));
+        assert!(warnings[1].contains("set more than once"));
+    }
+}
@@ -0,0 +1,963 @@
+use crate::delivery::{deliver, DeliveryError, DeliveryOptions, DeliveryReport};
+use crate::error::{AppError, ErrorCode};
+use crate::hid::{lock_or_recover, read_block, read_config};
+use crate::models::{Keyboard, KeyboardConfig};
+use crate::protocol::{TransferGroup, TransferKind};
+use crate::transport::{HidConnection, HidTransport};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Transport shared between the manager (enumeration) and the device workers (opening)
+pub type SharedTransport = Arc<Mutex<Box<dyn HidTransport>>>;
+
+/// Time to wait for a device to answer a configuration read
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request handled by a device worker
+enum Request {
+    Send {
+        groups: Vec<TransferGroup>,
+        options: DeliveryOptions,
+        reply: Sender<Result<DeliveryReport, DeliveryError>>,
+    },
+    Read {
+        keyboard: Keyboard,
+        reply: Sender<Result<KeyboardConfig, AppError>>,
+    },
+}
+
+impl Request {
+    /// Lighting-only updates can be dropped when a newer one is already queued (an empty
+    /// request sends nothing, so it cannot stand in for one)
+    fn is_lighting_update(&self) -> bool {
+        match self {
+            Request::Send { groups, .. } => {
+                !groups.is_empty()
+                    && groups.iter().all(|group| {
+                        matches!(
+                            group.kind,
+                            TransferKind::StandardLight | TransferKind::CustomLight
+                        )
+                    })
+            }
+            Request::Read { .. } => false,
+        }
+    }
+
+    /// Whether this request sends every kind of block `earlier` sends, so it can stand in
+    /// for it (a custom colors update cannot replace a standard light one, and vice versa)
+    fn covers(&self, earlier: &Request) -> bool {
+        match (self, earlier) {
+            (
+                Request::Send { groups, .. },
+                Request::Send {
+                    groups: earlier, ..
+                },
+            ) => {
+                let kinds: HashSet<TransferKind> = groups.iter().map(|group| group.kind).collect();
+                earlier.iter().all(|group| kinds.contains(&group.kind))
+            }
+            _ => false,
+        }
+    }
+
+    /// Answer the request without touching the device
+    fn skip(self, error: AppError) {
+        match self {
+            Request::Send { reply, .. } => {
+                reply.send(Err(DeliveryError::before_send(error))).ok();
+            }
+            Request::Read { reply, .. } => {
+                reply.send(Err(error)).ok();
+            }
+        }
+    }
+}
+
+/// Queued request with what the worker needs to know to skip it
+struct Job {
+    request: Request,
+    /// Cancellation generation of the worker when the request was queued
+    generation: u64,
+    /// Set by the caller when it stopped waiting for the result
+    abandoned: Arc<AtomicBool>,
+}
+
+/// Long-lived connection to one device, owned by a dedicated thread.
+///
+/// Requests are queued and executed in order on the same open handle, so writes
+/// no longer reopen the device or hold the manager lock. Lighting updates superseded
+/// by a newer queued one covering the same blocks are skipped, and the handle is
+/// reopened after a disconnect.
+/// The worker also remembers the blocks last applied to the device, so unchanged
+/// ones can be left out of later writes.
+pub struct DeviceWorker {
+    sender: Option<Sender<Job>>,
+    handle: Option<JoinHandle<()>>,
+    /// Bumped by `cancel` so queued requests of older generations are dropped
+    generation: Arc<AtomicU64>,
+}
+
+impl DeviceWorker {
+    /// Start a worker for the device at `path`; the device is opened on first use
+    pub fn spawn(path: String, transport: SharedTransport) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+        let thread_generation = generation.clone();
+
+        let handle = thread::spawn(move || {
+            WorkerLoop {
+                path,
+                transport,
+                connection: None,
+                applied: HashMap::new(),
+                generation: thread_generation,
+            }
+            .run(receiver)
+        });
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            generation,
+        }
+    }
+
+    /// Whether the worker thread is still alive (it stops for good after a panic)
+    pub fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Drop every request that is queued but not started yet
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Queue transfer groups and wait until they are delivered (or superseded)
+    pub fn send(
+        &self,
+        groups: Vec<TransferGroup>,
+        options: DeliveryOptions,
+    ) -> Result<DeliveryReport, DeliveryError> {
+        let (reply, response) = mpsc::channel();
+        let timeout = options.timeout();
+
+        self.submit(
+            Request::Send {
+                groups,
+                options,
+                reply,
+            },
+            &response,
+            timeout,
+        )
+        .map_err(DeliveryError::before_send)?
+    }
+
+    /// Queue a configuration read and wait for the result
+    pub fn read(&self, keyboard: Keyboard) -> Result<KeyboardConfig, AppError> {
+        let (reply, response) = mpsc::channel();
+        self.submit(
+            Request::Read { keyboard, reply },
+            &response,
+            DEFAULT_READ_TIMEOUT,
+        )?
+    }
+
+    /// Queue a request and wait for its reply, giving up after `timeout`
+    fn submit<T>(
+        &self,
+        request: Request,
+        response: &Receiver<T>,
+        timeout: Duration,
+    ) -> Result<T, AppError> {
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let job = Job {
+            request,
+            generation: self.generation.load(Ordering::SeqCst),
+            abandoned: abandoned.clone(),
+        };
+
+        let stopped = || AppError::Internal("Device worker stopped".to_string());
+
+        self.sender
+            .as_ref()
+            .ok_or_else(stopped)?
+            .send(job)
+            .map_err(|_| stopped())?;
+
+        response.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                // The worker skips the request if it has not started it yet
+                abandoned.store(true, Ordering::SeqCst);
+                AppError::Timeout {
+                    ms: timeout.as_millis() as u64,
+                }
+            }
+            RecvTimeoutError::Disconnected => stopped(),
+        })
+    }
+}
+
+impl Drop for DeviceWorker {
+    fn drop(&mut self) {
+        // Closing the queue makes the worker exit once pending requests are handled
+        self.sender.take();
+        self.cancel();
+
+        // A worker stuck in a driver call is left behind rather than blocking the caller
+        if let Some(handle) = self.handle.take() {
+            if handle.is_finished() {
+                handle.join().ok();
+            }
+        }
+    }
+}
+
+/// Failure of an operation run on the device handle
+trait OperationError {
+    fn code(&self) -> ErrorCode;
+}
+
+impl OperationError for AppError {
+    fn code(&self) -> ErrorCode {
+        AppError::code(self)
+    }
+}
+
+impl OperationError for DeliveryError {
+    fn code(&self) -> ErrorCode {
+        self.code
+    }
+}
+
+/// Frames of the last block of each kind the device accepted
+type AppliedBlocks = HashMap<TransferKind, Vec<Vec<u8>>>;
+
+/// State owned by the worker thread
+struct WorkerLoop {
+    path: String,
+    transport: SharedTransport,
+    connection: Option<Box<dyn HidConnection>>,
+    /// Frames of the last block of each kind the device accepted on this handle
+    applied: AppliedBlocks,
+    generation: Arc<AtomicU64>,
+}
+
+impl WorkerLoop {
+    fn run(mut self, receiver: Receiver<Job>) {
+        while let Ok(first) = receiver.recv() {
+            // Take everything already queued so superseded updates can be skipped
+            let mut batch = vec![first];
+            batch.extend(receiver.try_iter());
+
+            // A lighting update is superseded by a later one that will run and covers it
+            let superseded: Vec<bool> = (0..batch.len())
+                .map(|i| {
+                    batch[i].request.is_lighting_update()
+                        && batch[i + 1..].iter().any(|later| {
+                            self.is_live(later)
+                                && later.request.is_lighting_update()
+                                && later.request.covers(&batch[i].request)
+                        })
+                })
+                .collect();
+
+            for (job, superseded) in batch.into_iter().zip(superseded) {
+                if !self.is_live(&job) {
+                    job.request.skip(AppError::Cancelled);
+                    continue;
+                }
+
+                if superseded {
+                    if let Request::Send { reply, .. } = job.request {
+                        reply
+                            .send(Ok(DeliveryReport {
+                                superseded: true,
+                                ..Default::default()
+                            }))
+                            .ok();
+                    }
+                    continue;
+                }
+
+                self.handle(job.request);
+            }
+        }
+    }
+
+    /// Whether the job was neither cancelled nor abandoned by its caller
+    fn is_live(&self, job: &Job) -> bool {
+        job.generation == self.generation.load(Ordering::SeqCst)
+            && !job.abandoned.load(Ordering::SeqCst)
+    }
+
+    fn handle(&mut self, request: Request) {
+        match request {
+            Request::Send {
+                groups,
+                options,
+                reply,
+            } => {
+                reply.send(self.send(&groups, &options)).ok();
+            }
+            Request::Read { keyboard, reply } => {
+                let result = self
+                    .with_connection(|device, _| read_config(device, &keyboard))
+                    .and_then(|result| result);
+                reply.send(result).ok();
+            }
+        }
+    }
+
+    fn send(
+        &mut self,
+        groups: &[TransferGroup],
+        options: &DeliveryOptions,
+    ) -> Result<DeliveryReport, DeliveryError> {
+        // Unchanged blocks are picked once the handle is open, since (re)opening it
+        // forgets what the device holds
+        let result = self
+            .with_connection(|device, applied| {
+                let (pending, skipped) = split_unchanged(groups, applied, options);
+                let report = if pending.is_empty() {
+                    DeliveryReport::default()
+                } else {
+                    let pending_groups: Vec<TransferGroup> =
+                        pending.iter().map(|&i| groups[i].clone()).collect();
+                    // Report the failed group by its index in `groups`, not in `pending`
+                    deliver(device, &pending_groups, options, |device, kind| {
+                        read_block(device, kind).ok()
+                    })
+                    .map_err(|e| DeliveryError {
+                        group_index: pending[e.group_index],
+                        ..e
+                    })?
+                };
+
+                Ok(DeliveryReport { skipped, ..report })
+            })
+            .unwrap_or_else(|e| Err(DeliveryError::before_send(e)));
+
+        // `applied` is unchanged since the last attempt, so this is the split it used
+        let (pending, _) = split_unchanged(groups, &self.applied, options);
+        self.record_applied(groups, &pending, &result);
+        result
+    }
+
+    /// Remember the blocks the device now holds among the `pending` groups that were
+    /// sent; a failed group and the ones after it are forgotten since the device may
+    /// have kept part of them
+    fn record_applied(
+        &mut self,
+        groups: &[TransferGroup],
+        pending: &[usize],
+        result: &Result<DeliveryReport, DeliveryError>,
+    ) {
+        let delivered = match result {
+            Ok(_) => groups.len(),
+            Err(e) => e.group_index,
+        };
+
+        for &i in pending {
+            let group = &groups[i];
+            if i < delivered {
+                self.applied.insert(group.kind, group.reports.clone());
+            } else {
+                self.applied.remove(&group.kind);
+            }
+        }
+    }
+
+    /// Run `operation` on the open handle, reopening it once if the operation fails on
+    /// the link to the device (e.g. after the keyboard was unplugged and plugged back
+    /// in). Other failures, such as a verification mismatch, are returned as they are.
+    /// The operation also gets the blocks the device is known to hold on that handle.
+    fn with_connection<T, E: OperationError>(
+        &mut self,
+        operation: impl Fn(&dyn HidConnection, &AppliedBlocks) -> Result<T, E>,
+    ) -> Result<Result<T, E>, AppError> {
+        let reused = self.connection.is_some();
+        let (device, applied) = self.connect()?;
+        let result = operation(device, applied);
+
+        if reused
+            && result
+                .as_ref()
+                .is_err_and(|error| error.code().is_connection())
+        {
+            self.connection = None;
+            if let Ok((device, applied)) = self.connect() {
+                return Ok(operation(device, applied));
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn connect(&mut self) -> Result<(&dyn HidConnection, &AppliedBlocks), AppError> {
+        let device = match self.connection.take() {
+            Some(device) => device,
+            None => {
+                // The device may have been reconfigured while it was not open here
+                self.applied.clear();
+                lock_or_recover(&self.transport).open(&self.path)?
+            }
+        };
+
+        Ok((&**self.connection.insert(device), &self.applied))
+    }
+}
+
+/// Split `groups` into the indices of the ones to send and the kinds left out because
+/// the device already holds them (only under `skip_unchanged`)
+fn split_unchanged(
+    groups: &[TransferGroup],
+    applied: &AppliedBlocks,
+    options: &DeliveryOptions,
+) -> (Vec<usize>, Vec<TransferKind>) {
+    let (pending, skipped): (Vec<usize>, Vec<usize>) = (0..groups.len()).partition(|&i| {
+        !options.skip_unchanged || applied.get(&groups[i].kind) != Some(&groups[i].reports)
+    });
+
+    (
+        pending,
+        skipped.into_iter().map(|i| groups[i].kind).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{BUFFER_SIZE, REPORT_ID};
+    use crate::test_support::{device_info, options};
+    use crate::transport::{MockDevice, MockTransport};
+
+    const PATH: &str = "mock";
+
+    fn device() -> MockDevice {
+        MockDevice::new(device_info(PATH))
+    }
+
+    fn worker_loop(transport: &MockTransport) -> WorkerLoop {
+        WorkerLoop {
+            path: PATH.to_string(),
+            transport: Arc::new(Mutex::new(Box::new(transport.clone()))),
+            connection: None,
+            applied: HashMap::new(),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Group of `kind` whose reports are tagged with `tag` in their last byte
+    fn group(kind: TransferKind, tag: u8) -> TransferGroup {
+        let mut report = vec![0u8; BUFFER_SIZE];
+        report[BUFFER_SIZE - 1] = tag;
+        TransferGroup {
+            kind,
+            reports: vec![report; kind.packet_count()],
+        }
+    }
+
+    type Reply = Receiver<Result<DeliveryReport, DeliveryError>>;
+
+    /// Queue a send request as `DeviceWorker::send` does, without waiting for it
+    fn queue(jobs: &Sender<Job>, groups: Vec<TransferGroup>, options: DeliveryOptions) -> Reply {
+        queue_job(jobs, groups, options, 0, Arc::new(AtomicBool::new(false)))
+    }
+
+    fn queue_job(
+        jobs: &Sender<Job>,
+        groups: Vec<TransferGroup>,
+        options: DeliveryOptions,
+        generation: u64,
+        abandoned: Arc<AtomicBool>,
+    ) -> Reply {
+        let (reply, response) = mpsc::channel();
+        jobs.send(Job {
+            request: Request::Send {
+                groups,
+                options,
+                reply,
+            },
+            generation,
+            abandoned,
+        })
+        .unwrap();
+        response
+    }
+
+    /// Handle every queued request in one batch, like a worker that was busy meanwhile
+    fn run_batch(worker: WorkerLoop, jobs: Sender<Job>, receiver: Receiver<Job>) {
+        drop(jobs);
+        worker.run(receiver);
+    }
+
+    /// Tags of the reports the device received, one per report
+    fn sent_tags(device: &MockDevice) -> Vec<u8> {
+        device
+            .sent_reports()
+            .iter()
+            .map(|report| report[BUFFER_SIZE - 1])
+            .collect()
+    }
+
+    #[test]
+    fn queued_lighting_updates_are_coalesced() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let first = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        let second = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+        );
+        let third = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 3)],
+            options(),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        assert!(first.recv().unwrap().unwrap().superseded);
+        assert!(second.recv().unwrap().unwrap().superseded);
+        let last = third.recv().unwrap().unwrap();
+        assert!(!last.superseded);
+        assert_eq!(last.groups.len(), 1);
+        assert_eq!(sent_tags(&device), [3]);
+    }
+
+    #[test]
+    fn other_requests_are_not_coalesced() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let key_map = queue(&jobs, vec![group(TransferKind::KeyMap, 1)], options());
+        let lighting = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+        );
+        let mixed = queue(
+            &jobs,
+            vec![
+                group(TransferKind::StandardLight, 3),
+                group(TransferKind::KeyMap, 3),
+            ],
+            options(),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        assert!(!key_map.recv().unwrap().unwrap().superseded);
+        assert!(!lighting.recv().unwrap().unwrap().superseded);
+        assert!(!mixed.recv().unwrap().unwrap().superseded);
+        assert_eq!(sent_tags(&device).len(), 9 + 1 + 1 + 9);
+    }
+
+    #[test]
+    fn empty_request_does_not_supersede_a_lighting_update() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let lighting = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        let empty = queue(&jobs, Vec::new(), options());
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        assert!(!lighting.recv().unwrap().unwrap().superseded);
+        assert!(empty.recv().unwrap().unwrap().groups.is_empty());
+        assert_eq!(sent_tags(&device), [1]);
+    }
+
+    #[test]
+    fn lighting_updates_are_only_coalesced_into_one_covering_them() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let custom_colors = queue(&jobs, vec![group(TransferKind::CustomLight, 1)], options());
+        let standard = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+        );
+        let both = queue(
+            &jobs,
+            vec![
+                group(TransferKind::StandardLight, 3),
+                group(TransferKind::CustomLight, 3),
+            ],
+            options(),
+        );
+        let standard_again = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 4)],
+            options(),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        // Only the combined update sends every block the two before it send
+        assert!(custom_colors.recv().unwrap().unwrap().superseded);
+        assert!(standard.recv().unwrap().unwrap().superseded);
+        assert!(!both.recv().unwrap().unwrap().superseded);
+        assert!(!standard_again.recv().unwrap().unwrap().superseded);
+        let mut expected = vec![3; 1 + 7];
+        expected.push(4);
+        assert_eq!(sent_tags(&device), expected);
+    }
+
+    #[test]
+    fn custom_colors_update_is_kept_before_a_standard_light_one() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let custom_colors = queue(&jobs, vec![group(TransferKind::CustomLight, 1)], options());
+        let standard = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        assert!(!custom_colors.recv().unwrap().unwrap().superseded);
+        assert!(!standard.recv().unwrap().unwrap().superseded);
+        let mut expected = vec![1; 7];
+        expected.push(2);
+        assert_eq!(sent_tags(&device), expected);
+    }
+
+    #[test]
+    fn cancelled_update_does_not_supersede_an_earlier_one() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+
+        let first = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        let abandoned = queue_job(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+            0,
+            Arc::new(AtomicBool::new(true)),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        assert!(!first.recv().unwrap().unwrap().superseded);
+        assert!(abandoned.recv().unwrap().is_err());
+        assert_eq!(sent_tags(&device), [1]);
+    }
+
+    #[test]
+    fn unchanged_blocks_are_skipped_on_the_same_handle() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let mut worker = worker_loop(&transport);
+        let options = DeliveryOptions {
+            skip_unchanged: true,
+            ..options()
+        };
+
+        for tag in [1, 2] {
+            queue(
+                &jobs,
+                vec![
+                    group(TransferKind::StandardLight, tag),
+                    group(TransferKind::KeyMap, 1),
+                ],
+                options,
+            );
+        }
+        worker.handle(receiver.recv().unwrap().request);
+        let (reply, response) = mpsc::channel();
+        let Request::Send { groups, .. } = receiver.recv().unwrap().request else {
+            unreachable!()
+        };
+        worker.handle(Request::Send {
+            groups,
+            options,
+            reply,
+        });
+
+        let report = response.recv().unwrap().unwrap();
+        assert_eq!(report.skipped, [TransferKind::KeyMap]);
+        assert_eq!(report.groups.len(), 1);
+        let mut expected = vec![1; 1 + 9];
+        expected.push(2);
+        assert_eq!(sent_tags(&device), expected);
+    }
+
+    #[test]
+    fn failed_group_is_reported_by_its_index_in_the_request() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let mut worker = worker_loop(&transport);
+        let verified = DeliveryOptions {
+            skip_unchanged: true,
+            verify: true,
+            group_retries: 0,
+            ..options()
+        };
+
+        let first = queue(&jobs, vec![group(TransferKind::KeyMap, 1)], options());
+        worker.handle(receiver.recv().unwrap().request);
+        assert!(first.recv().unwrap().is_ok());
+
+        // The key map is skipped, then the keyboard answers the lighting read-back
+        // with other settings
+        let mut stored = vec![0u8; BUFFER_SIZE];
+        stored[0] = REPORT_ID;
+        stored[1] = 1;
+        stored[2] = 1;
+        (stored[3], stored[4]) = TransferKind::StandardLight.header();
+        device.push_response(stored);
+
+        let second = queue(
+            &jobs,
+            vec![
+                group(TransferKind::KeyMap, 1),
+                group(TransferKind::StandardLight, 2),
+            ],
+            verified,
+        );
+        worker.handle(receiver.recv().unwrap().request);
+
+        let error = second.recv().unwrap().unwrap_err();
+        assert_eq!(error.code, ErrorCode::VerificationFailed);
+        assert_eq!(error.kind, Some(TransferKind::StandardLight));
+        assert_eq!(error.group_index, 1);
+    }
+
+    #[test]
+    fn unchanged_blocks_are_resent_after_a_reconnect() {
+        let transport = MockTransport::new();
+        let unplugged = device();
+        transport.add_device(unplugged.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let mut worker = worker_loop(&transport);
+        let options = DeliveryOptions {
+            skip_unchanged: true,
+            ..options()
+        };
+
+        let first = queue(
+            &jobs,
+            vec![
+                group(TransferKind::StandardLight, 1),
+                group(TransferKind::KeyMap, 1),
+            ],
+            options,
+        );
+        worker.handle(receiver.recv().unwrap().request);
+        assert!(first.recv().unwrap().is_ok());
+
+        // The new device holds neither block, although the worker sent both before
+        unplugged.set_connected(false);
+        let replugged = device();
+        transport.add_device(replugged.clone());
+
+        let second = queue(
+            &jobs,
+            vec![
+                group(TransferKind::StandardLight, 2),
+                group(TransferKind::KeyMap, 1),
+            ],
+            options,
+        );
+        worker.handle(receiver.recv().unwrap().request);
+
+        let report = second.recv().unwrap().unwrap();
+        assert!(report.skipped.is_empty());
+        assert_eq!(report.groups.len(), 2);
+        let mut expected = vec![2];
+        expected.extend([1; 9]);
+        assert_eq!(sent_tags(&replugged), expected);
+    }
+
+    #[test]
+    fn handle_is_reused_and_reopened_after_a_replug() {
+        let transport = MockTransport::new();
+        let unplugged = device();
+        transport.add_device(unplugged.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let mut worker = worker_loop(&transport);
+
+        let first = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        worker.handle(receiver.recv().unwrap().request);
+        assert!(first.recv().unwrap().is_ok());
+
+        // Same path, new device: the cached handle fails and is replaced
+        unplugged.set_connected(false);
+        let replugged = device();
+        transport.add_device(replugged.clone());
+
+        let second = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            options(),
+        );
+        worker.handle(receiver.recv().unwrap().request);
+
+        assert!(second.recv().unwrap().is_ok());
+        assert_eq!(sent_tags(&unplugged), [1]);
+        assert_eq!(sent_tags(&replugged), [2]);
+    }
+
+    #[test]
+    fn verification_failure_is_not_sent_again_on_a_new_handle() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let mut worker = worker_loop(&transport);
+
+        let first = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        worker.handle(receiver.recv().unwrap().request);
+        assert!(first.recv().unwrap().is_ok());
+
+        // The keyboard answers the read-back with other lighting settings
+        let mut stored = vec![0u8; BUFFER_SIZE];
+        stored[0] = REPORT_ID;
+        stored[1] = 1;
+        stored[2] = 1;
+        (stored[3], stored[4]) = TransferKind::StandardLight.header();
+        device.push_response(stored);
+
+        let second = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 2)],
+            DeliveryOptions {
+                verify: true,
+                group_retries: 0,
+                ..options()
+            },
+        );
+        worker.handle(receiver.recv().unwrap().request);
+
+        let error = second.recv().unwrap().unwrap_err();
+        assert_eq!(error.code, ErrorCode::VerificationFailed);
+        let resent = sent_tags(&device).iter().filter(|tag| **tag == 2).count();
+        assert_eq!(resent, 1);
+    }
+
+    #[test]
+    fn missing_device_fails_before_sending() {
+        let transport = MockTransport::new();
+        let (jobs, receiver) = mpsc::channel();
+
+        let reply = queue(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 1)],
+            options(),
+        );
+        run_batch(worker_loop(&transport), jobs, receiver);
+
+        let error = reply.recv().unwrap().unwrap_err();
+        assert_eq!(error.kind, None);
+        assert_eq!(error.code, crate::error::ErrorCode::DeviceNotFound);
+    }
+
+    #[test]
+    fn cancelled_and_abandoned_requests_are_skipped() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let (jobs, receiver) = mpsc::channel();
+        let worker = worker_loop(&transport);
+        worker.generation.store(1, Ordering::SeqCst);
+
+        let cancelled = queue_job(
+            &jobs,
+            vec![group(TransferKind::KeyMap, 1)],
+            options(),
+            0,
+            Arc::new(AtomicBool::new(false)),
+        );
+        let abandoned = queue_job(
+            &jobs,
+            vec![group(TransferKind::KeyMap, 2)],
+            options(),
+            1,
+            Arc::new(AtomicBool::new(true)),
+        );
+        let current = queue_job(
+            &jobs,
+            vec![group(TransferKind::StandardLight, 3)],
+            options(),
+            1,
+            Arc::new(AtomicBool::new(false)),
+        );
+        run_batch(worker, jobs, receiver);
+
+        for reply in [cancelled, abandoned] {
+            let error = reply.recv().unwrap().unwrap_err();
+            assert_eq!(error.code, crate::error::ErrorCode::Cancelled);
+        }
+        assert!(current.recv().unwrap().is_ok());
+        assert_eq!(sent_tags(&device), [3]);
+    }
+
+    #[test]
+    fn send_times_out_while_the_device_is_busy() {
+        let transport = MockTransport::new();
+        let device = device();
+        transport.add_device(device.clone());
+        let shared: SharedTransport = Arc::new(Mutex::new(Box::new(transport)));
+        let worker = DeviceWorker::spawn(PATH.to_string(), shared.clone());
+        let options = DeliveryOptions {
+            timeout_ms: 50,
+            ..options()
+        };
+
+        // Opening the device waits for the transport, held here like a long enumeration
+        let busy = lock_or_recover(&shared);
+        let error = worker
+            .send(vec![group(TransferKind::StandardLight, 1)], options)
+            .unwrap_err();
+        drop(busy);
+
+        assert_eq!(error.code, crate::error::ErrorCode::Timeout);
+        assert!(worker
+            .send(vec![group(TransferKind::StandardLight, 2)], options)
+            .is_ok());
+    }
+}
@@ -0,0 +1,18 @@
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn bundled_definitions_pass_lint() {
+    let keyboards = Path::new(env!("CARGO_MANIFEST_DIR")).join("keyboards");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rk-defs-lint"))
+        .arg(&keyboards)
+        .output()
+        .expect("failed to run rk-defs-lint");
+
+    assert!(
+        output.status.success(),
+        "rk-defs-lint reported errors:\n{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}